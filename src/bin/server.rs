@@ -1,16 +1,55 @@
-use glob::Pattern;
-use server::{Handler, Method, Response, Server, Status};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use server::{Handler, Method, ParamError, Response, Route, Server, Status};
+
+#[derive(Default)]
+struct Stats {
+    hits: AtomicUsize,
+}
 
 fn main() {
     let mut server = Server::new(String::from("localhost:8080"));
-    server.handlers.register(Handler {
-        methods: vec![Method::GET, Method::POST],
-        pattern: Pattern::new("/ok/*").unwrap(),
-        func: |r, w| -> Result<usize, std::io::Error> {
-            let mut resp = Response::new(Status::Ok, &format!("hello from {}!", r.uri.path));
+    server.set_state(Stats::default());
+    server
+        .handlers
+        .register(Handler::new(
+            vec![Method::GET, Method::POST],
+            Route::new("/ok/*rest").unwrap(),
+            |r, w| {
+                let hits = match r.state::<Stats>() {
+                    Some(s) => s.hits.fetch_add(1, Ordering::Relaxed) + 1,
+                    None => 0,
+                };
+                let mut resp = Response::new(
+                    Status::Ok,
+                    &format!("hello from {}! ({} hits)", r.uri.path, hits),
+                );
+
+                w.send(&mut resp)
+            },
+        ))
+        .unwrap();
+    server
+        .handlers
+        .register(Handler::returning(
+            vec![Method::GET],
+            Route::new("/users/:id").unwrap(),
+            |r| -> Result<_, ParamError> {
+                let id: u32 = r.param_as("id")?;
+                Ok(format!("user #{}", id))
+            },
+        ))
+        .unwrap();
+
+    let shutdown = server.shutdown_handle();
+    ctrlc::set_handler(move || {
+        println!("received termination signal");
+        shutdown.shutdown();
+    })
+    .expect("couldn't install signal handler");
 
-            resp.write_to(w)
-        },
-    });
-    server.run()
+    if let Err(e) = server.run() {
+        println!("{}", e);
+        std::process::exit(1);
+    }
 }
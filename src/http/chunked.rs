@@ -0,0 +1,219 @@
+use std::{
+    error::Error,
+    fmt::Display,
+    io::{BufRead, Read},
+};
+
+use super::Headers;
+
+/// Longest chunk-size line (size, extensions and CRLF) or trailer line accepted.
+const MAX_LINE: u64 = 4096;
+
+#[derive(Debug)]
+pub enum ChunkedError {
+    Io(std::io::Error),
+    Malformed,
+    TooLarge,
+    /// The trailer section has more fields or bytes than allowed.
+    TrailersTooLarge,
+}
+
+impl Display for ChunkedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "couldn't read chunked body: {}", e),
+            Self::Malformed => write!(f, "chunked body is malformed"),
+            Self::TooLarge => write!(f, "chunked body exceeds the size limit"),
+            Self::TrailersTooLarge => write!(f, "trailer section exceeds the size limit"),
+        }
+    }
+}
+
+impl Error for ChunkedError {}
+
+impl From<std::io::Error> for ChunkedError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// Reads a body sent with `Transfer-Encoding: chunked` (RFC 9112 section 7.1)
+/// and returns the decoded payload. Chunk extensions are ignored, trailer
+/// fields are added to `trailers`. Fails with [`ChunkedError::TooLarge`] as
+/// soon as the decoded body would grow beyond `max_size` bytes, and with
+/// [`ChunkedError::TrailersTooLarge`] if the trailer section has more than
+/// `max_trailers` fields or, with the empty line ending it, more than
+/// `max_trailer_bytes` bytes.
+pub fn read_chunked<R: BufRead>(
+    reader: &mut R,
+    max_size: usize,
+    trailers: &mut Headers,
+    max_trailer_bytes: usize,
+    max_trailers: usize,
+) -> Result<Vec<u8>, ChunkedError> {
+    let mut body = Vec::new();
+    let mut line = String::new();
+
+    loop {
+        read_line(reader, &mut line, MAX_LINE)?;
+
+        // 1a;name=value\r\n
+        let size = line.split(';').next().unwrap_or_default().trim();
+        if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(ChunkedError::Malformed);
+        }
+        let size = usize::from_str_radix(size, 16).map_err(|_| ChunkedError::TooLarge)?;
+        if size == 0 {
+            break;
+        }
+        if size > max_size - body.len() {
+            return Err(ChunkedError::TooLarge);
+        }
+
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..])?;
+
+        let mut crlf = [0u8; 2];
+        reader.read_exact(&mut crlf)?;
+        if &crlf != b"\r\n" {
+            return Err(ChunkedError::Malformed);
+        }
+    }
+
+    // trailer section, terminated by an empty line
+    let mut trailer_bytes = 0;
+    loop {
+        let max = MAX_LINE.min((max_trailer_bytes - trailer_bytes) as u64);
+        match read_line(reader, &mut line, max) {
+            Err(ChunkedError::TooLarge) => return Err(ChunkedError::TrailersTooLarge),
+            r => r?,
+        }
+        trailer_bytes += line.len() + 2;
+        if line.is_empty() {
+            return Ok(body);
+        }
+        if trailers.len() == max_trailers {
+            return Err(ChunkedError::TrailersTooLarge);
+        }
+        trailers
+            .add_from_line(&line)
+            .map_err(|_| ChunkedError::Malformed)?;
+    }
+}
+
+/// Reads one CRLF terminated line of at most `max` bytes into `line`, without
+/// the line ending.
+fn read_line<R: BufRead>(reader: &mut R, line: &mut String, max: u64) -> Result<(), ChunkedError> {
+    line.clear();
+    if max == 0 {
+        return Err(ChunkedError::TooLarge);
+    }
+    let n = reader.by_ref().take(max).read_line(line)?;
+    if n == 0 {
+        return Err(ChunkedError::Io(std::io::ErrorKind::UnexpectedEof.into()));
+    }
+    if !line.ends_with("\r\n") {
+        return Err(if n as u64 == max {
+            ChunkedError::TooLarge
+        } else {
+            ChunkedError::Malformed
+        });
+    }
+    line.truncate(line.len() - 2);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{read_chunked, ChunkedError};
+    use crate::Headers;
+
+    fn decode(s: &str, max_size: usize) -> Result<(Vec<u8>, Headers), ChunkedError> {
+        let mut trailers = Headers::new();
+        let body = read_chunked(
+            &mut Cursor::new(s.as_bytes()),
+            max_size,
+            &mut trailers,
+            1024,
+            4,
+        )?;
+        Ok((body, trailers))
+    }
+
+    #[test]
+    fn chunks_and_trailers() {
+        let (body, trailers) = decode(
+            "4\r\nWiki\r\n6;name=value\r\npedia \r\nE\r\nin \r\n\r\nchunks.\r\n0\r\nExpires: never\r\n\r\n",
+            1024,
+        )
+        .unwrap();
+
+        assert_eq!(body, b"Wikipedia in \r\n\r\nchunks.");
+        assert_eq!(trailers.get("expires".to_string()).unwrap(), "never");
+    }
+
+    #[test]
+    fn leaves_rest_of_stream() {
+        let mut c = Cursor::new("3\r\nabc\r\n0\r\n\r\nGET / HTTP/1.1\r\n".as_bytes());
+        let body = read_chunked(&mut c, 1024, &mut Headers::new(), 1024, 4).unwrap();
+
+        assert_eq!(body, b"abc");
+        assert_eq!(c.position(), 13);
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(matches!(
+            decode("x\r\n", 1024),
+            Err(ChunkedError::Malformed)
+        ));
+        assert!(matches!(
+            decode("+3\r\nabc\r\n0\r\n\r\n", 1024),
+            Err(ChunkedError::Malformed)
+        ));
+        assert!(matches!(
+            decode("3\r\nabcd\r\n0\r\n\r\n", 1024),
+            Err(ChunkedError::Malformed)
+        ));
+        assert!(matches!(decode("3\r\nab", 1024), Err(ChunkedError::Io(_))));
+        assert!(matches!(
+            decode("8\r\nabcdefgh\r\n0\r\n\r\n", 4),
+            Err(ChunkedError::TooLarge)
+        ));
+        assert!(matches!(
+            decode("ffffffffffffffffffff\r\n", 4),
+            Err(ChunkedError::TooLarge)
+        ));
+    }
+
+    #[test]
+    fn trailer_limits() {
+        let read = |s: &str, max_bytes, max_fields| {
+            read_chunked(
+                &mut Cursor::new(s.as_bytes()),
+                1024,
+                &mut Headers::new(),
+                max_bytes,
+                max_fields,
+            )
+        };
+
+        assert!(read("0\r\nA: 1\r\nB: 2\r\n\r\n", 14, 2).is_ok());
+        assert!(matches!(
+            read("0\r\nA: 1\r\nB: 2\r\n\r\n", 13, 2),
+            Err(ChunkedError::TrailersTooLarge)
+        ));
+        assert!(matches!(
+            read("0\r\nA: 1\r\nB: 2\r\n\r\n", 14, 1),
+            Err(ChunkedError::TrailersTooLarge)
+        ));
+        assert!(matches!(
+            read("0\r\n\r\n", 0, 0),
+            Err(ChunkedError::TrailersTooLarge)
+        ));
+    }
+}
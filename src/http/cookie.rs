@@ -0,0 +1,314 @@
+use std::{
+    error::Error,
+    fmt::Display,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::headers::is_token;
+
+/// Where a cookie is sent along with requests started from other sites.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    /// Browsers only accept this for [`Cookie::secure`] cookies.
+    None,
+}
+
+impl Display for SameSite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Strict => write!(f, "Strict"),
+            Self::Lax => write!(f, "Lax"),
+            Self::None => write!(f, "None"),
+        }
+    }
+}
+
+/// A cookie to set with [`Response::set_cookie`](super::Response::set_cookie).
+/// It displays as the value of its `Set-Cookie` header (RFC 6265 section 4.1).
+///
+/// ```
+/// # use std::time::Duration;
+/// # use server::{Cookie, SameSite};
+/// let c = Cookie::new("session", "abc123")
+///     .path("/")
+///     .max_age(Duration::from_secs(3600))
+///     .http_only(true)
+///     .same_site(SameSite::Lax);
+/// assert_eq!(
+///     c.to_string(),
+///     "session=abc123; Path=/; Max-Age=3600; HttpOnly; SameSite=Lax"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    expires: Option<SystemTime>,
+    max_age: Option<Duration>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+}
+
+impl Cookie {
+    pub fn new(name: &str, value: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            value: value.to_string(),
+            path: None,
+            domain: None,
+            expires: None,
+            max_age: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+        }
+    }
+
+    /// A cookie that makes the client delete the cookie `name`. Set the same
+    /// path and domain as the cookie had.
+    pub fn removal(name: &str) -> Self {
+        Self::new(name, "").max_age(Duration::ZERO)
+    }
+
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = Some(path.to_string());
+        self
+    }
+
+    pub fn domain(mut self, domain: &str) -> Self {
+        self.domain = Some(domain.to_string());
+        self
+    }
+
+    /// When the cookie expires, sent with a precision of seconds. Clients
+    /// prefer [`Cookie::max_age`] if both are set.
+    pub fn expires(mut self, at: SystemTime) -> Self {
+        self.expires = Some(at);
+        self
+    }
+
+    /// How long the cookie is kept, zero deletes it.
+    pub fn max_age(mut self, age: Duration) -> Self {
+        self.max_age = Some(age);
+        self
+    }
+
+    /// Only send the cookie over HTTPS.
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    /// Hide the cookie from scripts in the browser.
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Checks that the name is a token and that the value and attributes
+    /// can't end the header or add attributes of their own.
+    pub fn validate(&self) -> Result<(), CookieError> {
+        if !is_token(&self.name) {
+            return Err(CookieError::InvalidName(self.name.clone()));
+        }
+
+        // cookie-octet, optionally in double quotes
+        let value = self
+            .value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(&self.value);
+        let is_octet = |b: u8| b.is_ascii_graphic() && !b"\",;\\".contains(&b);
+        if !value.bytes().all(is_octet) {
+            return Err(CookieError::InvalidValue(self.value.clone()));
+        }
+
+        for attr in [&self.path, &self.domain].into_iter().flatten() {
+            if attr.bytes().any(|b| b.is_ascii_control() || b == b';') {
+                return Err(CookieError::InvalidAttribute(attr.clone()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for Cookie {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if let Some(p) = &self.path {
+            write!(f, "; Path={}", p)?;
+        }
+        if let Some(d) = &self.domain {
+            write!(f, "; Domain={}", d)?;
+        }
+        if let Some(t) = self.expires {
+            write!(f, "; Expires={}", http_date(t))?;
+        }
+        if let Some(a) = self.max_age {
+            write!(f, "; Max-Age={}", a.as_secs())?;
+        }
+        if self.secure {
+            write!(f, "; Secure")?;
+        }
+        if self.http_only {
+            write!(f, "; HttpOnly")?;
+        }
+        if let Some(s) = self.same_site {
+            write!(f, "; SameSite={}", s)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CookieError {
+    InvalidName(String),
+    InvalidValue(String),
+    /// A path or domain with a `;` or control character.
+    InvalidAttribute(String),
+}
+
+impl Display for CookieError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidName(n) => write!(f, "invalid cookie name '{}'", n),
+            Self::InvalidValue(v) => write!(f, "invalid cookie value '{}'", v),
+            Self::InvalidAttribute(a) => write!(f, "invalid cookie attribute '{}'", a),
+        }
+    }
+}
+
+impl Error for CookieError {}
+
+/// Parses the value of a `Cookie` header, `a=1; b=2`, into name/value pairs.
+/// Pairs without a `=` are skipped, quotes around values are removed.
+pub(crate) fn parse_cookies(header: &str) -> impl Iterator<Item = (&str, &str)> {
+    header.split(';').filter_map(|pair| {
+        let (name, value) = pair.split_once('=')?;
+        let name = name.trim();
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(value);
+
+        (!name.is_empty()).then_some((name, value))
+    })
+}
+
+/// Formats `t` as an IMF-fixdate, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`
+/// (RFC 9110 section 5.6.7). Times before 1970 are sent as the epoch.
+fn http_date(t: SystemTime) -> String {
+    const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let secs = t.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let days = secs / 86400;
+    let (h, m, s) = (secs % 86400 / 3600, secs % 3600 / 60, secs % 60);
+
+    // civil date from days since the epoch, see
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAYS[(days % 7) as usize],
+        day,
+        MONTHS[(month - 1) as usize],
+        year,
+        h,
+        m,
+        s
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{http_date, parse_cookies, Cookie, CookieError, SameSite};
+
+    #[test]
+    fn set_cookie_value() {
+        let c = Cookie::new("id", "a3fWa")
+            .domain("example.com")
+            .path("/docs")
+            .expires(UNIX_EPOCH + Duration::from_secs(1_445_412_480))
+            .secure(true)
+            .http_only(true)
+            .same_site(SameSite::Strict);
+        assert_eq!(
+            c.to_string(),
+            "id=a3fWa; Path=/docs; Domain=example.com; Expires=Wed, 21 Oct 2015 07:28:00 GMT; Secure; HttpOnly; SameSite=Strict"
+        );
+
+        assert_eq!(Cookie::removal("id").to_string(), "id=; Max-Age=0");
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(http_date(UNIX_EPOCH), "Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(
+            http_date(UNIX_EPOCH + Duration::from_secs(784_111_777)),
+            "Sun, 06 Nov 1994 08:49:37 GMT"
+        );
+        // leap day
+        assert_eq!(
+            http_date(UNIX_EPOCH + Duration::from_secs(951_782_400)),
+            "Tue, 29 Feb 2000 00:00:00 GMT"
+        );
+    }
+
+    #[test]
+    fn validate() {
+        assert_eq!(Cookie::new("a", "\"quoted\"").validate(), Ok(()));
+        assert_eq!(
+            Cookie::new("a b", "1").validate(),
+            Err(CookieError::InvalidName("a b".to_string()))
+        );
+        assert_eq!(
+            Cookie::new("a", "1; Secure").validate(),
+            Err(CookieError::InvalidValue("1; Secure".to_string()))
+        );
+        assert_eq!(
+            Cookie::new("a", "1").path("/; Domain=evil").validate(),
+            Err(CookieError::InvalidAttribute("/; Domain=evil".to_string()))
+        );
+    }
+
+    #[test]
+    fn parse() {
+        let pairs: Vec<_> = parse_cookies("a=1; b=\"two\";c=; broken; =x; d=e=f").collect();
+        assert_eq!(pairs, [("a", "1"), ("b", "two"), ("c", ""), ("d", "e=f")]);
+    }
+}
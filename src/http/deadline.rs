@@ -0,0 +1,47 @@
+use std::{
+    io::{ErrorKind, Read},
+    net::TcpStream,
+    time::Instant,
+};
+
+/// A connection whose reads fail with [`ErrorKind::TimedOut`] once a deadline
+/// has passed, however the reads before it were spread out. A plain read
+/// timeout restarts with every read, so a client sending a byte at a time
+/// could keep a request open forever.
+pub(crate) struct DeadlineStream {
+    stream: TcpStream,
+    deadline: Option<Instant>,
+}
+
+impl DeadlineStream {
+    pub fn new(stream: TcpStream) -> Self {
+        Self {
+            stream,
+            deadline: None,
+        }
+    }
+
+    /// Sets the deadline for all following reads, `None` leaves the read
+    /// timeout of the stream as it is.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    pub fn get_ref(&self) -> &TcpStream {
+        &self.stream
+    }
+}
+
+impl Read for DeadlineStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if let Some(deadline) = self.deadline {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err(ErrorKind::TimedOut.into());
+            }
+            self.stream.set_read_timeout(Some(left))?;
+        }
+
+        self.stream.read(buf)
+    }
+}
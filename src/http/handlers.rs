@@ -1,63 +1,205 @@
-use std::io::Write;
+use std::{io::Write, sync::Arc};
 
 use crate::{
-    http::{Method, Request},
-    Response, Status,
+    http::{router::Node, Method, Request},
+    Group, IntoResponse, Middleware, Next, Params, Response, ResponseWriter, Route, RouteError,
+    Status, TargetForm,
 };
-use glob::Pattern;
+
+/// The function called for a request, may be a closure capturing state.
+pub type HandlerFunc<T> =
+    Box<dyn Fn(Request, &mut ResponseWriter<T>) -> Result<usize, std::io::Error> + Send + Sync>;
 
 pub struct Handler<T: Write> {
     pub methods: Vec<Method>,
-    pub pattern: Pattern,
-    pub func: fn(Request, &mut T) -> Result<usize, std::io::Error>,
+    pub pattern: Route,
+    pub func: HandlerFunc<T>,
+    /// Middleware that runs before `func`, in order.
+    pub middleware: Vec<Arc<dyn Middleware<T>>>,
 }
 
 impl<T: Write> Handler<T> {
-    pub fn handle(&self, r: Request, w: &mut T) -> Result<usize, std::io::Error> {
-        (self.func)(r, w)
+    pub fn new<F>(methods: Vec<Method>, pattern: Route, func: F) -> Self
+    where
+        F: Fn(Request, &mut ResponseWriter<T>) -> Result<usize, std::io::Error>
+            + Send
+            + Sync
+            + 'static,
+    {
+        Self {
+            methods,
+            pattern,
+            func: Box::new(func),
+            middleware: vec![],
+        }
+    }
+
+    /// Creates a handler from a function that returns its response instead of
+    /// writing it, the server sends whatever it returns. Returning a `Result`
+    /// lets handlers use `?` with errors that implement [`IntoResponse`]:
+    ///
+    /// ```
+    /// # use server::{Handler, Method, ParamError, Route, Status};
+    /// let h = Handler::<Vec<u8>>::returning(
+    ///     vec![Method::GET],
+    ///     Route::new("/users/:id").unwrap(),
+    ///     |r| -> Result<_, ParamError> {
+    ///         let id: u32 = r.param_as("id")?;
+    ///         Ok((Status::Ok, format!("user #{}", id)))
+    ///     },
+    /// );
+    /// ```
+    pub fn returning<F, R>(methods: Vec<Method>, pattern: Route, func: F) -> Self
+    where
+        F: Fn(Request) -> R + Send + Sync + 'static,
+        R: IntoResponse,
+    {
+        Self::new(methods, pattern, move |r, w| {
+            w.send(&mut func(r).into_response())
+        })
     }
+
+    /// Runs the handler's middleware and then the handler itself.
+    pub fn handle(&self, r: Request, w: &mut ResponseWriter<T>) -> Result<usize, std::io::Error> {
+        Next::new(&self.middleware, &*self.func).run(r, w)
+    }
+}
+
+/// The result of looking up a request in [`Handlers`].
+pub enum Match<'a, T: Write> {
+    /// A handler is registered for the path and method.
+    Handler(&'a Handler<T>, Params),
+    /// Handlers are registered for the path, but none for the method. Holds the
+    /// methods that are allowed, including `OPTIONS`.
+    MethodNotAllowed(Vec<Method>),
+    NotFound,
 }
 
 pub struct Handlers<T: Write> {
-    handlers: Vec<Handler<T>>,
+    routes: Node<T>,
     default_handler: Handler<T>,
 }
 
+impl<T: Write> Default for Handlers<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T: Write> Handlers<T> {
     pub fn new() -> Self {
-        let h = Handlers {
-            handlers: vec![],
-            default_handler: Handler {
-                methods: vec![Method::GET],
-                pattern: Pattern::new("/").unwrap(),
-                func: |r: Request, w: &mut T| {
+        Handlers {
+            routes: Node::default(),
+            default_handler: Handler::new(
+                vec![Method::GET],
+                Route::new("/*path").unwrap(),
+                |r: Request, w: &mut ResponseWriter<T>| {
                     let mut resp = Response::new(
                         Status::NotFound,
-                        &format!(
-                            "no handler found for {} {}",
-                            r.method.to_string(),
-                            r.uri.path
-                        ),
+                        &format!("no handler found for {} {}", r.method, r.uri.path),
                     );
 
-                    resp.write_to(w)
+                    w.send(&mut resp)
                 },
-            },
+            ),
+        }
+    }
+
+    /// Finds the handler for a request and the parameters its route captured
+    /// from `path`.
+    pub fn get(&self, m: &Method, path: &str) -> Match<'_, T> {
+        let mut params = Params::default();
+        let Some(handlers) = self.routes.lookup(path, &mut params) else {
+            return Match::NotFound;
+        };
+
+        let find = |m: &Method| handlers.iter().find(|h| h.methods.contains(m));
+        // HEAD is answered by the GET handler unless it has its own
+        let handler = match m {
+            Method::HEAD => find(m).or_else(|| find(&Method::GET)),
+            _ => find(m),
         };
-        return h;
+
+        match handler {
+            Some(h) => Match::Handler(h, params),
+            None => Match::MethodNotAllowed(allowed_methods(handlers)),
+        }
     }
 
-    pub fn get(&self, m: &Method, uri: &str) -> (bool, &Handler<T>) {
-        for h in self.handlers.iter() {
-            if h.methods.contains(m) && h.pattern.matches(uri) {
-                return (true, h);
+    /// Routes a request to its handler, `HEAD` requests go to the `GET`
+    /// handler if there is no handler for `HEAD`. Requests for a registered
+    /// path with another method are answered with `405 Method Not Allowed`, or
+    /// with the allowed methods for `OPTIONS`, unless a handler is registered
+    /// for `OPTIONS` itself. `OPTIONS *` is answered with `200 OK` and the
+    /// methods of all handlers. Everything else goes to the default handler.
+    pub fn handle(
+        &self,
+        mut r: Request,
+        w: &mut ResponseWriter<T>,
+    ) -> Result<usize, std::io::Error> {
+        if r.uri.form == TargetForm::Asterisk {
+            let mut resp = Response::new(Status::Ok, "");
+            set_allow(&mut resp, &allowed_methods(self.routes.all()));
+            return w.send(&mut resp);
+        }
+
+        match self.get(&r.method, &r.uri.path) {
+            Match::Handler(h, params) => {
+                r.params = params;
+                h.handle(r, w)
+            }
+            Match::MethodNotAllowed(allowed) => {
+                let mut resp = match r.method {
+                    Method::OPTIONS => Response::new(Status::Ok, ""),
+                    _ => Response::new(
+                        Status::MethodNotAllowed,
+                        &format!("{} is not allowed for {}", r.method, r.uri.path),
+                    ),
+                };
+                set_allow(&mut resp, &allowed);
+                w.send(&mut resp)
             }
+            Match::NotFound => self.default_handler.handle(r, w),
         }
+    }
 
-        (false, &self.default_handler)
+    /// Adds a handler. Fails if it conflicts with a handler registered before,
+    /// see [`RouteError::Conflict`].
+    pub fn register(&mut self, h: Handler<T>) -> Result<(), RouteError> {
+        self.routes.insert(h)
     }
 
-    pub fn register(&mut self, h: Handler<T>) {
-        self.handlers.insert(0, h)
+    /// Adds all handlers of a group.
+    pub fn register_group(&mut self, g: Group<T>) -> Result<(), RouteError> {
+        for h in g.into_handlers()? {
+            self.register(h)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The methods of `handlers`, with `HEAD` if there is a `GET` handler and
+/// `OPTIONS`, which are always answered.
+fn allowed_methods<'a, T: Write + 'a>(
+    handlers: impl IntoIterator<Item = &'a Handler<T>>,
+) -> Vec<Method> {
+    let mut allowed: Vec<Method> = handlers
+        .into_iter()
+        .flat_map(|h| h.methods.iter().copied())
+        .collect();
+    if allowed.contains(&Method::GET) {
+        allowed.push(Method::HEAD);
     }
+    allowed.push(Method::OPTIONS);
+    allowed.sort();
+    allowed.dedup();
+
+    allowed
+}
+
+fn set_allow(resp: &mut Response, allowed: &[Method]) {
+    let allow: Vec<String> = allowed.iter().map(|m| m.to_string()).collect();
+    resp.headers
+        .add("Allow".to_string(), Some(allow.join(", ")));
 }
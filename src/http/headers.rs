@@ -1,12 +1,13 @@
-use std::{collections::{HashMap, hash_map::RandomState}, fmt::Display, error::Error};
+use std::{error::Error, fmt::Display};
 
-#[derive(Debug)]
-#[derive(Default)]
+/// Header fields in the order they were added. Names are looked up ignoring
+/// case but sent the way they were added, and a name may occur more than once,
+/// e.g. for `Set-Cookie`.
+#[derive(Debug, Clone)]
 pub struct Headers {
-    map: HashMap<String, Option<String>, RandomState>
+    entries: Vec<(String, Option<String>)>,
 }
 
-
 #[derive(Debug)]
 pub enum HeaderError {
     NotFound,
@@ -19,87 +20,223 @@ impl Display for HeaderError {
         match self {
             Self::NoValue => write!(f, "header is defined but has no value"),
             Self::NotFound => write!(f, "header is not defined"),
-            Self::InvalidValue => write!(f, "header is defined but has an invalid value, e.g. invalid int"),
+            Self::InvalidValue => write!(
+                f,
+                "header is defined but has an invalid value, e.g. invalid int"
+            ),
         }
     }
 }
 
 impl Error for HeaderError {}
 
-
-
-
-impl std::string::ToString for Headers {
-    fn to_string(&self) -> String {
-        let mut s = String::new();
-        for (k,v) in &self.map {
+impl Display for Headers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (k, v) in &self.entries {
             let vs = match v {
-                Some(s) => s.to_owned(),
-                None => "".to_string(),
+                Some(s) => s.as_str(),
+                None => "",
             };
-            s.push_str(&format!("{}: {}\r\n", k,vs));
+            write!(f, "{}: {}\r\n", k, vs)?;
         }
-        s.push_str("\r\n");
-        
-        s
+        write!(f, "\r\n")
     }
 }
 
-impl Headers {
-    pub fn new() -> Self {
-        Headers {map: HashMap::new()}
-    }
-
-    pub fn default() -> Self {
-        let mut h = Headers {map: HashMap::new()};
+impl Default for Headers {
+    fn default() -> Self {
+        let mut h = Headers::new();
         h.add("Server".to_string(), Some("rusty-server".to_string()));
-        h.add("Connection".to_string(), Some("close".to_string()));
-        
+
         h
     }
+}
 
+impl Headers {
+    pub fn new() -> Self {
+        Headers { entries: vec![] }
+    }
+
+    /// The `Content-Length` value. Repeated fields are accepted only if they
+    /// all have the same value, and only digits are, not e.g. a leading `+`
+    /// that other parsers may reject.
     pub fn content_length(&self) -> Result<usize, HeaderError> {
-        match self.get(String::from("content-length")) {
-            Err(e) => Err(e),
-            Ok(v) =>  v.parse::<usize>().map_err(|_| HeaderError::InvalidValue)
+        let v = self.get(String::from("content-length"))?;
+        if self.get_all("content-length").any(|o| o != v) || !v.bytes().all(|b| b.is_ascii_digit())
+        {
+            return Err(HeaderError::InvalidValue);
         }
 
+        v.parse::<usize>().map_err(|_| HeaderError::InvalidValue)
     }
 
     pub fn server(&self) -> Result<String, HeaderError> {
         self.get(String::from("server"))
     }
 
-    pub fn add(&mut self, k: String, v: Option<String>) ->Option<Option<String>>{
-        self.map.insert(k.to_lowercase(), v)
+    /// Sets `k` to `v`, replacing all values it had before. Returns the first
+    /// of the replaced values.
+    pub fn add(&mut self, k: String, v: Option<String>) -> Option<Option<String>> {
+        let Some(i) = self.position(&k) else {
+            self.entries.push((k, v));
+            return None;
+        };
+
+        let old = std::mem::replace(&mut self.entries[i], (k, v));
+        let mut n = 0;
+        self.entries.retain(|(name, _)| {
+            n += 1;
+            n <= i + 1 || !name.eq_ignore_ascii_case(&old.0)
+        });
+
+        Some(old.1)
+    }
+
+    /// Sets `k` to `v`, replacing all values it had before.
+    pub fn insert(&mut self, k: &str, v: &str) {
+        self.add(k.to_string(), Some(v.to_string()));
+    }
+
+    /// Adds another value for `k` after the ones it already has.
+    pub fn append(&mut self, k: &str, v: &str) {
+        self.entries.push((k.to_string(), Some(v.to_string())));
     }
 
+    /// Removes all values of `k` and returns the first of them.
+    pub fn remove(&mut self, k: &str) -> Option<Option<String>> {
+        let first = self.position(k).map(|i| self.entries[i].1.clone());
+        self.entries
+            .retain(|(name, _)| !name.eq_ignore_ascii_case(k));
+
+        first
+    }
+
+    /// The first value of `k`, use [`Headers::get_all`] for headers that can
+    /// occur more than once.
     pub fn get(&self, k: String) -> Result<String, HeaderError> {
-        let v = self.map.get(&k.to_ascii_lowercase());
-        match v {
-            Some(o) => {
-                match o {
-                    Some(v) => Ok(v.to_owned()),
-                    None => Err(HeaderError::NoValue),
-                }
+        match self.position(&k) {
+            Some(i) => match &self.entries[i].1 {
+                Some(v) => Ok(v.to_owned()),
+                None => Err(HeaderError::NoValue),
             },
-            None => Err(HeaderError::NotFound)
+            None => Err(HeaderError::NotFound),
         }
     }
 
-    pub fn add_from_line(&mut self, l: &str) -> Result<(String, Option<String>),()> {
-        //"User-Agent: curl/7.81.0\r\n"
-        let Some((k,v)) = l.split_once(':').or(Some((l, ""))) else {
-            return Err(())
+    /// All values of `k` in the order they were added, empty ones included.
+    pub fn get_all<'a>(&'a self, k: &'a str) -> impl Iterator<Item = &'a str> {
+        self.entries
+            .iter()
+            .filter(move |(name, _)| name.eq_ignore_ascii_case(k))
+            .map(|(_, v)| v.as_deref().unwrap_or_default())
+    }
+
+    /// Whether `k` has at least one value.
+    pub fn contains(&self, k: &str) -> bool {
+        self.position(k).is_some()
+    }
+
+    /// All fields in order, with names as they were added.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_deref().unwrap_or_default()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn position(&self, k: &str) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|(name, _)| name.eq_ignore_ascii_case(k))
+    }
+
+    /// Appends a field from a header line such as `User-Agent: curl/7.81.0\r\n`.
+    #[allow(clippy::result_unit_err)]
+    pub fn add_from_line(&mut self, l: &str) -> Result<(String, Option<String>), ()> {
+        let Some((k, v)) = l.split_once(':').or(Some((l, ""))) else {
+            return Err(());
         };
 
-        let v = match v {
+        let v = match v.trim() {
             "" => None,
-            s => Some(s.trim().to_string()),
+            s => Some(s.to_string()),
         };
 
-        self.add(k.to_string(), v.clone());
+        self.entries.push((k.to_string(), v.clone()));
 
         Ok((k.to_string(), v))
-    } 
+    }
+}
+
+/// Whether `s` is a token (RFC 9110 section 5.6.2), as header names and
+/// cookie names have to be.
+pub(crate) fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HeaderError, Headers};
+
+    #[test]
+    fn multiple_values() {
+        let mut h = Headers::new();
+        h.add_from_line("Accept: text/html\r\n").unwrap();
+        h.add_from_line("Via: 1.1 a\r\n").unwrap();
+        h.add_from_line("accept: */*\r\n").unwrap();
+        h.append("Set-Cookie", "a=1");
+        h.append("Set-Cookie", "b=2");
+
+        assert_eq!(h.get("ACCEPT".to_string()).unwrap(), "text/html");
+        assert_eq!(
+            h.get_all("Accept").collect::<Vec<_>>(),
+            ["text/html", "*/*"]
+        );
+        assert_eq!(h.get_all("set-cookie").count(), 2);
+        assert_eq!(
+            h.to_string(),
+            "Accept: text/html\r\nVia: 1.1 a\r\naccept: */*\r\nSet-Cookie: a=1\r\nSet-Cookie: b=2\r\n\r\n"
+        );
+
+        // insert replaces all values and keeps the position of the first
+        h.insert("ACCEPT", "application/json");
+        assert_eq!(
+            h.iter().collect::<Vec<_>>(),
+            [
+                ("ACCEPT", "application/json"),
+                ("Via", "1.1 a"),
+                ("Set-Cookie", "a=1"),
+                ("Set-Cookie", "b=2"),
+            ]
+        );
+
+        assert_eq!(h.remove("set-cookie"), Some(Some("a=1".to_string())));
+        assert!(!h.contains("Set-Cookie"));
+        assert_eq!(h.len(), 2);
+    }
+
+    #[test]
+    fn content_length() {
+        let mut h = Headers::new();
+        assert!(matches!(h.content_length(), Err(HeaderError::NotFound)));
+
+        h.append("Content-Length", "5");
+        h.append("Content-Length", "5");
+        assert_eq!(h.content_length().unwrap(), 5);
+
+        h.append("Content-Length", "6");
+        assert!(matches!(h.content_length(), Err(HeaderError::InvalidValue)));
+
+        h.insert("Content-Length", "+3");
+        assert!(matches!(h.content_length(), Err(HeaderError::InvalidValue)));
+    }
 }
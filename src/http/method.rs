@@ -1,4 +1,4 @@
-#[derive(Debug, Default, PartialEq, Eq)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Method {
     #[default]
     GET,
@@ -12,8 +12,8 @@ pub enum Method {
     PATCH,
 }
 
-impl std::string::ToString for Method {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for Method {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let s = match self {
             Self::GET => "GET",
             Self::POST => "POST",
@@ -26,7 +26,7 @@ impl std::string::ToString for Method {
             Self::PATCH => "PATCH",
         };
 
-        s.to_string()
+        write!(f, "{}", s)
     }
 }
 
@@ -0,0 +1,127 @@
+use std::{io::Write, sync::Arc};
+
+use super::{Handler, Request, ResponseWriter, Route, RouteError};
+
+/// Code that runs around handlers, e.g. for logging, authentication or CORS.
+///
+/// A middleware gets the request before the handler does and decides what
+/// happens to it: it can change the request and pass it on with
+/// [`Next::run`], answer it itself by sending a response through `w` without
+/// calling `next`, or change the response the handler sends by registering a
+/// hook with [`ResponseWriter::on_response`].
+///
+/// ```
+/// use std::io::Write;
+/// use server::{Middleware, Next, Request, ResponseWriter};
+///
+/// struct PoweredBy;
+///
+/// impl<T: Write> Middleware<T> for PoweredBy {
+///     fn handle(
+///         &self,
+///         r: Request,
+///         w: &mut ResponseWriter<T>,
+///         next: Next<'_, T>,
+///     ) -> Result<usize, std::io::Error> {
+///         w.on_response(|resp| {
+///             resp.headers.add("X-Powered-By".to_string(), Some("rust".to_string()));
+///         });
+///         next.run(r, w)
+///     }
+/// }
+/// ```
+pub trait Middleware<T: Write>: Send + Sync {
+    fn handle(
+        &self,
+        r: Request,
+        w: &mut ResponseWriter<T>,
+        next: Next<'_, T>,
+    ) -> Result<usize, std::io::Error>;
+}
+
+type Endpoint<'a, T> = &'a dyn Fn(Request, &mut ResponseWriter<T>) -> Result<usize, std::io::Error>;
+
+/// The rest of the middleware chain, ending with the handler.
+pub struct Next<'a, T: Write> {
+    chain: &'a [Arc<dyn Middleware<T>>],
+    endpoint: Endpoint<'a, T>,
+}
+
+impl<'a, T: Write> Next<'a, T> {
+    pub(crate) fn new(chain: &'a [Arc<dyn Middleware<T>>], endpoint: Endpoint<'a, T>) -> Self {
+        Self { chain, endpoint }
+    }
+
+    /// Passes the request on to the next middleware, or the handler.
+    pub fn run(self, r: Request, w: &mut ResponseWriter<T>) -> Result<usize, std::io::Error> {
+        match self.chain.split_first() {
+            Some((m, rest)) => m.handle(r, w, Next::new(rest, self.endpoint)),
+            None => (self.endpoint)(r, w),
+        }
+    }
+}
+
+/// Handlers registered under a common path prefix that share middleware.
+///
+/// ```
+/// # use server::{Group, Handler, Handlers, Method, Response, Route, Status};
+/// # let mut handlers = Handlers::<Vec<u8>>::new();
+/// let mut admin = Group::new("/admin").unwrap();
+/// // admin.wrap(RequireLogin);
+/// admin.register(Handler::new(vec![Method::GET], Route::new("/users").unwrap(), |_, w| {
+///     w.send(&mut Response::new(Status::Ok, "all users"))
+/// }));
+/// handlers.register_group(admin).unwrap();
+/// ```
+pub struct Group<T: Write> {
+    prefix: Route,
+    middleware: Vec<Arc<dyn Middleware<T>>>,
+    handlers: Vec<Handler<T>>,
+}
+
+impl<T: Write> Group<T> {
+    /// Creates a group for routes below `prefix`, which may contain parameters
+    /// but no wildcard.
+    pub fn new(prefix: &str) -> Result<Self, RouteError> {
+        let prefix = Route::new(prefix)?;
+        if prefix.has_wildcard() {
+            return Err(RouteError::WildcardNotLast);
+        }
+
+        Ok(Self {
+            prefix,
+            middleware: vec![],
+            handlers: vec![],
+        })
+    }
+
+    /// Adds a middleware that runs for every handler of the group, after the
+    /// ones added before it.
+    pub fn wrap<M: Middleware<T> + 'static>(&mut self, m: M) {
+        self.middleware.push(Arc::new(m));
+    }
+
+    /// Adds a handler, its route is relative to the group's prefix.
+    pub fn register(&mut self, h: Handler<T>) {
+        self.handlers.push(h);
+    }
+
+    /// The handlers with the prefix prepended to their route and the group's
+    /// middleware in front of their own.
+    pub(crate) fn into_handlers(self) -> Result<Vec<Handler<T>>, RouteError> {
+        let Self {
+            prefix,
+            middleware,
+            handlers,
+        } = self;
+
+        handlers
+            .into_iter()
+            .map(|mut h| {
+                h.pattern = prefix.join(&h.pattern)?;
+                h.middleware.splice(0..0, middleware.iter().cloned());
+                Ok(h)
+            })
+            .collect()
+    }
+}
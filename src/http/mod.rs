@@ -3,15 +3,36 @@ mod method;
 mod headers;
 mod version;
 mod uri;
+mod query;
 mod response;
+mod status;
 mod handlers;
 mod server;
+mod pool;
+mod writer;
+mod shutdown;
+mod chunked;
+mod route;
+mod router;
+mod middleware;
+mod deadline;
+mod vhost;
+mod cookie;
 
-pub use request::Request;
+pub use request::{Limits, Request, RequestParseError};
 pub use headers::Headers;
-pub use uri::Uri;
+pub use uri::{TargetForm, Uri, UriError};
+pub use query::QueryParams;
 pub use version::HttpVersion;
 pub use method::Method;
-pub use response::{Response, Status};
-pub use handlers::{Handlers, Handler};
-pub use server::Server;
\ No newline at end of file
+pub use response::{IntoResponse, Response};
+pub use status::{CustomStatus, InvalidStatus, Status};
+pub use handlers::{Handler, HandlerFunc, Handlers, Match};
+pub use server::{BoundServer, Config, Server, ServerError};
+pub use pool::OverloadPolicy;
+pub use writer::{BodyWriter, ResponseWriter};
+pub use shutdown::ShutdownHandle;
+pub use route::{ParamError, Params, Route, RouteError};
+pub use middleware::{Group, Middleware, Next};
+pub use vhost::{HostPattern, HostPatternError};
+pub use cookie::{Cookie, CookieError, SameSite};
@@ -0,0 +1,164 @@
+use std::{
+    net::TcpStream,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// What the accept loop does with a new connection when every worker is busy
+/// and the queue is full.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OverloadPolicy {
+    /// Answer the connection with `503 Service Unavailable` and close it.
+    #[default]
+    Reject,
+    /// Stop accepting until a slot in the queue frees up.
+    Block,
+}
+
+/// A fixed set of worker threads fed with accepted connections through a
+/// bounded queue.
+pub(crate) struct WorkerPool {
+    workers: Vec<JoinHandle<()>>,
+    queue: Option<SyncSender<TcpStream>>,
+    policy: OverloadPolicy,
+}
+
+impl WorkerPool {
+    pub fn new<F>(
+        size: usize,
+        queue_depth: usize,
+        policy: OverloadPolicy,
+        f: F,
+    ) -> Result<Self, std::io::Error>
+    where
+        F: Fn(TcpStream) + Send + Sync + 'static,
+    {
+        let (tx, rx) = mpsc::sync_channel::<TcpStream>(queue_depth);
+        let rx = Arc::new(Mutex::new(rx));
+        let f = Arc::new(f);
+
+        let mut pool = Self {
+            workers: Vec::with_capacity(size),
+            queue: Some(tx),
+            policy,
+        };
+        for id in 0..size.max(1) {
+            let rx = Arc::clone(&rx);
+            let f = Arc::clone(&f);
+            let worker = thread::Builder::new()
+                .name(format!("worker-{}", id))
+                .spawn(move || Self::work(rx, f))?;
+            pool.workers.push(worker);
+        }
+
+        Ok(pool)
+    }
+
+    fn work<F: Fn(TcpStream)>(rx: Arc<Mutex<Receiver<TcpStream>>>, f: Arc<F>) {
+        loop {
+            // only hold the lock while waiting for the next connection, not while handling it
+            let next = match rx.lock() {
+                Ok(rx) => rx.recv(),
+                Err(_) => return,
+            };
+            match next {
+                Ok(stream) => {
+                    // a panicking handler must not take the worker down with it
+                    if panic::catch_unwind(AssertUnwindSafe(|| f(stream))).is_err() {
+                        println!("worker panicked while handling a connection");
+                    }
+                }
+                Err(_) => return, // queue closed, pool is shutting down
+            }
+        }
+    }
+
+    /// Hands a connection to the pool. If the queue is full and the policy is
+    /// [`OverloadPolicy::Reject`] the stream is given back to the caller.
+    pub fn submit(&self, stream: TcpStream) -> Result<(), TcpStream> {
+        let Some(queue) = &self.queue else {
+            return Err(stream);
+        };
+
+        match self.policy {
+            OverloadPolicy::Block => queue.send(stream).map_err(|e| e.0),
+            OverloadPolicy::Reject => queue.try_send(stream).map_err(|e| match e {
+                TrySendError::Full(s) | TrySendError::Disconnected(s) => s,
+            }),
+        }
+    }
+
+    /// Stops taking new connections and waits up to `timeout` for the workers
+    /// to finish the ones already queued or in progress. Returns `false` if
+    /// some workers were still busy when the time ran out; those are left
+    /// running detached.
+    pub fn shutdown(mut self, timeout: Duration) -> bool {
+        drop(self.queue.take());
+
+        let deadline = Instant::now() + timeout;
+        while self.workers.iter().any(|w| !w.is_finished()) {
+            if Instant::now() >= deadline {
+                self.workers.clear();
+                return false;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        for w in self.workers.drain(..) {
+            let _ = w.join();
+        }
+        true
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        // closing the queue makes every worker return once it is drained
+        drop(self.queue.take());
+        for w in self.workers.drain(..) {
+            let _ = w.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{TcpListener, TcpStream},
+        sync::{mpsc, Mutex},
+    };
+
+    use super::{OverloadPolicy, WorkerPool};
+
+    #[test]
+    fn reject_when_queue_full() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let connect = || TcpStream::connect(addr).unwrap();
+
+        let (started_tx, started_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let (started_tx, release_rx) = (Mutex::new(started_tx), Mutex::new(release_rx));
+
+        let pool = WorkerPool::new(1, 1, OverloadPolicy::Reject, move |_| {
+            started_tx.lock().unwrap().send(()).unwrap();
+            let _ = release_rx.lock().unwrap().recv();
+        })
+        .unwrap();
+
+        // the only worker picks up the first connection and blocks
+        assert!(pool.submit(connect()).is_ok());
+        started_rx.recv().unwrap();
+
+        // the second one fits into the queue, the third one doesn't
+        assert!(pool.submit(connect()).is_ok());
+        assert!(pool.submit(connect()).is_err());
+
+        drop(release_tx);
+    }
+}
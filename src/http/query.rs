@@ -0,0 +1,161 @@
+use std::str::FromStr;
+
+use serde::de::DeserializeOwned;
+
+use super::route::{parse_param, ParamError};
+use super::{uri::decode, UriError};
+
+/// The parameters of a query string, in the order they were sent. A name may
+/// occur more than once, e.g. `?tag=a&tag=b`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct QueryParams {
+    pairs: Vec<(String, String)>,
+    raw: String,
+}
+
+impl QueryParams {
+    /// Parses a query string without the leading `?`. Names and values are
+    /// percent-decoded, with `+` as a space. A name without `=` gets an empty
+    /// value.
+    pub fn parse(query: &str) -> Result<Self, UriError> {
+        let mut pairs = vec![];
+        for p in query.split('&').filter(|p| !p.is_empty()) {
+            let (k, v) = p.split_once('=').unwrap_or((p, ""));
+            pairs.push((decode(k, true)?, decode(v, true)?));
+        }
+
+        Ok(Self {
+            pairs,
+            raw: query.to_string(),
+        })
+    }
+
+    /// The first value of `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.pairs
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// All values of `name` in the order they were sent.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> {
+        self.pairs
+            .iter()
+            .filter(move |(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Parses the first value of `name`, see [`ParamError`] for the error.
+    pub fn get_as<T: FromStr>(&self, name: &str) -> Result<T, ParamError> {
+        parse_param(name, self.get(name))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    /// Deserializes the whole query into `T`. Fields of type `Vec` collect
+    /// every value of a repeated name, `Option` fields may be left out:
+    ///
+    /// ```
+    /// # use server::Uri;
+    /// #[derive(serde::Deserialize)]
+    /// struct Search {
+    ///     q: String,
+    ///     #[serde(default)]
+    ///     tag: Vec<String>,
+    ///     page: Option<u32>,
+    /// }
+    ///
+    /// let uri: Uri = "/search?q=rust&tag=web&tag=http".parse().unwrap();
+    /// let s: Search = uri.params.deserialize().unwrap();
+    /// assert_eq!(s.tag, ["web", "http"]);
+    /// assert_eq!(s.page, None);
+    /// ```
+    pub fn deserialize<T: DeserializeOwned>(&self) -> Result<T, ParamError> {
+        serde_html_form::from_str(&self.raw).map_err(|e| ParamError::Query(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::QueryParams;
+    use crate::ParamError;
+
+    #[test]
+    fn multiple_values() {
+        let q = QueryParams::parse("tag=a&x=1&tag=b&flag&tag=").unwrap();
+
+        assert_eq!(q.get("tag"), Some("a"));
+        assert_eq!(q.get_all("tag").collect::<Vec<_>>(), ["a", "b", ""]);
+        assert_eq!(q.get("flag"), Some(""));
+        assert_eq!(q.get("missing"), None);
+        assert_eq!(
+            q.iter().map(|(n, _)| n).collect::<Vec<_>>(),
+            ["tag", "x", "tag", "flag", "tag"]
+        );
+
+        assert!(QueryParams::parse("").unwrap().is_empty());
+        assert!(QueryParams::parse("&&").unwrap().is_empty());
+    }
+
+    #[test]
+    fn typed() {
+        let q = QueryParams::parse("page=2&size=big").unwrap();
+
+        assert_eq!(q.get_as::<u32>("page"), Ok(2));
+        assert_eq!(
+            q.get_as::<u32>("size"),
+            Err(ParamError::Invalid {
+                name: "size".to_string(),
+                value: "big".to_string()
+            })
+        );
+        assert_eq!(
+            q.get_as::<u32>("offset"),
+            Err(ParamError::Missing("offset".to_string()))
+        );
+    }
+
+    #[test]
+    fn deserialize() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Filter {
+            q: String,
+            #[serde(default)]
+            status: Vec<String>,
+            limit: Option<u8>,
+        }
+
+        let q = QueryParams::parse("q=a+b%26c&status=open&status=closed&limit=10").unwrap();
+        assert_eq!(
+            q.deserialize::<Filter>().unwrap(),
+            Filter {
+                q: "a b&c".to_string(),
+                status: vec!["open".to_string(), "closed".to_string()],
+                limit: Some(10),
+            }
+        );
+
+        let q = QueryParams::parse("limit=1000").unwrap();
+        assert!(matches!(
+            q.deserialize::<Filter>(),
+            Err(ParamError::Query(_))
+        ));
+    }
+}
@@ -1,13 +1,22 @@
 use std::{
-    io::{BufRead, BufReader, Read},
-    net::TcpStream,
+    any::Any,
+    error::Error,
+    fmt::Display,
+    io::{BufRead, ErrorKind, Read, Write},
     str::FromStr,
+    sync::Arc,
 };
 
+use super::chunked::{read_chunked, ChunkedError};
+use super::cookie::parse_cookies;
+use super::headers::{is_token, HeaderError};
+use super::route::{parse_param, ParamError, Params};
+use super::uri::parse_authority;
 use super::Headers;
 use super::HttpVersion;
 use super::Method;
-use super::Uri;
+use super::Status;
+use super::{TargetForm, Uri};
 
 #[derive(Debug)]
 pub struct Request {
@@ -15,72 +24,333 @@ pub struct Request {
     pub uri: Uri,
     pub headers: Headers,
     pub payload: Vec<u8>,
+    /// Trailer fields sent after a chunked body.
+    pub trailers: Headers,
     pub version: HttpVersion,
+    /// Parameters captured from the path by the route of the handler.
+    pub params: Params,
+    /// Application state of the server that read the request.
+    pub(crate) state: Option<Arc<dyn Any + Send + Sync>>,
     // pub stream: TcpStream,
 }
 
+/// Bounds on the size of a request, checked while it is read so that no
+/// client can make the server buffer more than this.
+#[derive(Debug, Clone)]
+pub struct Limits {
+    /// Longest request line, including the line ending. Answered with
+    /// `414 URI Too Long`.
+    pub max_request_line: usize,
+    /// Most bytes of all header lines together, including the empty line that
+    /// ends them. The trailer section of a chunked body counts as well.
+    /// Answered with `431 Request Header Fields Too Large`.
+    pub max_header_bytes: usize,
+    /// Most header and trailer fields. Answered with
+    /// `431 Request Header Fields Too Large`.
+    pub max_headers: usize,
+    /// Largest body, as announced by `Content-Length` or after decoding a
+    /// chunked body. Answered with `413 Content Too Large`.
+    pub max_body_size: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_request_line: 8 * 1024,
+            max_header_bytes: 64 * 1024,
+            max_headers: 100,
+            max_body_size: 8 * 1024 * 1024,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum RequestParseError {
+    /// Reading from the connection failed.
     CantReadRequest,
+    /// The connection was closed before the request was complete.
+    Truncated,
+    /// The request didn't arrive completely within the time allowed.
+    Timeout,
     RequestMalformed,
     UrlMalformed,
     VersionMalformed,
     UnknownMethod,
     UnsupportedVersion,
+    UriTooLong,
+    HeadersTooLarge,
+    MissingHost,
+    /// The `Host` header is repeated or not a valid host and port.
+    InvalidHost,
+    /// The body can't be framed from `Content-Length` and
+    /// `Transfer-Encoding`, or the chunked encoding is broken.
+    BodyMalformed,
+    UnsupportedTransferEncoding,
+    BodyTooLarge,
+}
+
+impl RequestParseError {
+    /// The status to answer the request with, `None` if the client can't be
+    /// answered anymore.
+    pub fn status(&self) -> Option<Status> {
+        let status = match self {
+            Self::CantReadRequest => return None,
+            Self::Timeout => Status::RequestTimeout,
+            Self::Truncated
+            | Self::RequestMalformed
+            | Self::UrlMalformed
+            | Self::VersionMalformed
+            | Self::MissingHost
+            | Self::InvalidHost
+            | Self::BodyMalformed => Status::BadRequest,
+            Self::UnknownMethod | Self::UnsupportedTransferEncoding => Status::NotImplemented,
+            Self::UnsupportedVersion => Status::HttpVersionNotSupported,
+            Self::UriTooLong => Status::UriTooLong,
+            Self::HeadersTooLarge => Status::RequestHeaderFieldsTooLarge,
+            Self::BodyTooLarge => Status::ContentTooLarge,
+        };
+
+        Some(status)
+    }
+}
+
+impl Display for RequestParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let msg = match self {
+            Self::CantReadRequest => "couldn't read the request",
+            Self::Truncated => "connection was closed before the request was complete",
+            Self::Timeout => "request took too long to arrive",
+            Self::RequestMalformed => "request is malformed",
+            Self::UrlMalformed => "request target is malformed",
+            Self::VersionMalformed => "HTTP version is malformed",
+            Self::UnknownMethod => "request method is not supported",
+            Self::UnsupportedVersion => "HTTP version is not supported",
+            Self::UriTooLong => "request target is too long",
+            Self::HeadersTooLarge => "request headers are too large",
+            Self::MissingHost => "Host header is missing",
+            Self::InvalidHost => "Host header is invalid",
+            Self::BodyMalformed => "request body is malformed",
+            Self::UnsupportedTransferEncoding => "transfer encoding is not supported",
+            Self::BodyTooLarge => "request body is too large",
+        };
+
+        write!(f, "{}", msg)
+    }
+}
+
+impl Error for RequestParseError {}
+
+impl From<std::io::Error> for RequestParseError {
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            ErrorKind::UnexpectedEof => Self::Truncated,
+            ErrorKind::WouldBlock | ErrorKind::TimedOut => Self::Timeout,
+            _ => Self::CantReadRequest,
+        }
+    }
+}
+
+impl From<ChunkedError> for RequestParseError {
+    fn from(e: ChunkedError) -> Self {
+        match e {
+            ChunkedError::Io(e) => e.into(),
+            ChunkedError::Malformed => Self::BodyMalformed,
+            ChunkedError::TooLarge => Self::BodyTooLarge,
+            ChunkedError::TrailersTooLarge => Self::HeadersTooLarge,
+        }
+    }
 }
 
 impl Request {
-    pub fn new(s: &TcpStream) -> Result<Self, ()> {
+    /// Reads a request with the default [`Limits`]. Reading stops at the end
+    /// of the body, whatever follows it, e.g. a pipelined request, stays in
+    /// `reader`.
+    pub fn new<R: BufRead>(reader: &mut R) -> Result<Self, RequestParseError> {
+        Self::with_limits(reader, &Limits::default())
+    }
+
+    pub fn with_limits<R: BufRead>(
+        reader: &mut R,
+        limits: &Limits,
+    ) -> Result<Self, RequestParseError> {
         let mut line_buf = String::new();
-        let mut reader = BufReader::new(s);
 
         // read first line (GET /index HTTP/1.1)
-        reader.read_line(&mut line_buf).map_err(|_| ())?;
-        let (method, uri, version) = Self::parse_first_line(line_buf.as_str()).unwrap();
-        println!("{:?} {:?} {:?}", method, uri, version);
+        if !read_line(reader, &mut line_buf, limits.max_request_line)? {
+            return Err(RequestParseError::UriTooLong);
+        }
+        let (method, uri, version) = Self::parse_first_line(line_buf.as_str())?;
 
         // Read headers
         let mut headers = Headers::new();
+        let mut header_bytes = 0;
+        let mut count = 0;
         loop {
-            line_buf.clear();
-            let Ok(_) = reader.read_line(&mut line_buf) else {
-                break;
-            };
+            let limit = limits.max_header_bytes - header_bytes;
+            if limit == 0 || !read_line(reader, &mut line_buf, limit)? {
+                return Err(RequestParseError::HeadersTooLarge);
+            }
+            header_bytes += line_buf.len();
 
             match line_buf.as_str() {
-                "\r\n" => {
-                    // println!("Empty!");
-                    break;
-                }
+                "\r\n" => break,
                 l => {
-                    if let Ok((k, v)) = headers.add_from_line(l) {
-                        println!("{}: {:?}", k, v);
-                    };
+                    count += 1;
+                    if count > limits.max_headers {
+                        return Err(RequestParseError::HeadersTooLarge);
+                    }
+                    // the name has to be a token right up to the colon. This also
+                    // rejects obs-fold continuation lines, which start with a space
+                    // or tab, as a proxy that unfolds them would frame the message
+                    // differently (RFC 9112 section 5)
+                    match l.split_once(':') {
+                        Some((k, _)) if is_token(k) => {}
+                        _ => return Err(RequestParseError::RequestMalformed),
+                    }
+                    headers
+                        .add_from_line(l)
+                        .map_err(|_| RequestParseError::RequestMalformed)?;
                 }
             }
         }
 
-        // Read rest of the request according to specified Content-Length
-        let cl = headers.content_length().unwrap();
-        let mut payload = vec![0u8; cl];
+        // HTTP/1.1 requires exactly one Host, RFC 9112 section 3.2
+        let hosts: Vec<&str> = headers.get_all("host").collect();
+        match hosts[..] {
+            [] if version == HttpVersion::HTTP1_1 => return Err(RequestParseError::MissingHost),
+            [h] if !h.is_empty() && parse_authority(h).is_err() => {
+                return Err(RequestParseError::InvalidHost)
+            }
+            [_, _, ..] => return Err(RequestParseError::InvalidHost),
+            _ => {}
+        }
 
-        if cl > 0 {
-            let Ok(_) = reader.read(&mut payload) else {
-                println!("Couldn't read payload");
-                return Err(());
+        // Read the body, its length is determined as described in RFC 9112 section 6.3
+        let mut trailers = Headers::new();
+        let payload = if headers.contains("transfer-encoding") {
+            // a message with both headers could be framed differently by a proxy in
+            // front of us
+            if headers.contains("content-length") {
+                return Err(RequestParseError::BodyMalformed);
+            }
+            // and anything but plain chunked can't be framed at all
+            let te: Vec<&str> = headers.get_all("transfer-encoding").collect();
+            if !te.join(",").trim().eq_ignore_ascii_case("chunked") {
+                return Err(RequestParseError::UnsupportedTransferEncoding);
+            }
+
+            // trailer fields count against the limits of the header section
+            read_chunked(
+                reader,
+                limits.max_body_size,
+                &mut trailers,
+                limits.max_header_bytes - header_bytes,
+                limits.max_headers - count,
+            )?
+        } else {
+            let cl = match headers.content_length() {
+                Ok(cl) => cl,
+                Err(HeaderError::NotFound) => 0,
+                Err(_) => return Err(RequestParseError::BodyMalformed),
             };
-        }
+            if cl > limits.max_body_size {
+                return Err(RequestParseError::BodyTooLarge);
+            }
+            let mut payload = vec![0u8; cl];
+            reader.read_exact(&mut payload)?;
+
+            payload
+        };
 
         Ok(Request {
             method,
             uri,
             headers,
             payload,
+            trailers,
             version,
+            params: Params::default(),
+            state: None,
             // stream: reader.into_inner(),
         })
     }
 
+    /// Parses a request from `buf` and returns it together with the bytes
+    /// following it.
+    pub fn parse(buf: &[u8]) -> Result<(Self, &[u8]), RequestParseError> {
+        let mut rest = buf;
+        let r = Self::new(&mut rest)?;
+
+        Ok((r, rest))
+    }
+
+    /// The value of the path parameter `name`, e.g. `id` for a handler
+    /// registered for `/users/:id`.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.get(name)
+    }
+
+    /// Parses the path parameter `name`, see [`ParamError`] for the error.
+    pub fn param_as<T: FromStr>(&self, name: &str) -> Result<T, ParamError> {
+        parse_param(name, self.param(name))
+    }
+
+    /// The application state set with [`Server::set_state`](crate::Server::set_state),
+    /// or `None` if there is none or it isn't an `S`.
+    pub fn state<S: Any + Send + Sync>(&self) -> Option<&S> {
+        self.state.as_deref()?.downcast_ref()
+    }
+
+    /// The cookies the client sent, as name/value pairs in the order they were
+    /// sent.
+    pub fn cookies(&self) -> Vec<(String, String)> {
+        self.headers
+            .get_all("cookie")
+            .flat_map(parse_cookies)
+            .map(|(n, v)| (n.to_string(), v.to_string()))
+            .collect()
+    }
+
+    /// The value of the first cookie called `name`.
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.headers
+            .get_all("cookie")
+            .flat_map(parse_cookies)
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v.to_string())
+    }
+
+    /// The lowercased host the request is for, without the port: the host of
+    /// an absolute-form target, which takes precedence, or of the `Host`
+    /// header.
+    pub fn host(&self) -> Option<String> {
+        if let Some(h) = &self.uri.host {
+            return Some(h.trim_end_matches('.').to_string());
+        }
+
+        let header = self.headers.get(String::from("host")).ok()?;
+        let (host, _) = parse_authority(&header).ok()?;
+        let host = host.trim_end_matches('.');
+
+        (!host.is_empty()).then(|| host.to_ascii_lowercase())
+    }
+
+    /// Whether the client wants the connection to stay open after this request,
+    /// based on the version defaults and the `Connection` header.
+    pub fn keep_alive(&self) -> bool {
+        let has_option = |o: &str| {
+            self.headers
+                .get_all("connection")
+                .flat_map(|v| v.split(','))
+                .any(|t| t.trim().eq_ignore_ascii_case(o))
+        };
+
+        match self.version {
+            HttpVersion::HTTP1_1 => !has_option("close"),
+            HttpVersion::HTTP1_0 => has_option("keep-alive"),
+        }
+    }
+
     pub fn parse_first_line(l: &str) -> Result<(Method, Uri, HttpVersion), RequestParseError> {
         let mut tokens = l.split_ascii_whitespace();
         let method = match tokens.next() {
@@ -92,37 +362,103 @@ impl Request {
             Some(p) => Uri::from_str(p).map_err(|_| RequestParseError::UrlMalformed)?,
             None => return Err(RequestParseError::RequestMalformed),
         };
+        // the authority and asterisk forms belong to one method each
+        let valid_form = match uri.form {
+            TargetForm::Authority => method == Method::CONNECT,
+            TargetForm::Asterisk => method == Method::OPTIONS,
+            TargetForm::Origin | TargetForm::Absolute => method != Method::CONNECT,
+        };
+        if !valid_form {
+            return Err(RequestParseError::UrlMalformed);
+        }
 
         let version = match tokens.next() {
-            Some(p) => HttpVersion::from_str(p).map_err(|_| RequestParseError::VersionMalformed)?,
+            Some(p) => HttpVersion::from_str(p).map_err(|_| {
+                // HTTP/2.0 is a valid version, just not one we speak
+                match p.strip_prefix("HTTP/").map(str::as_bytes) {
+                    Some([major, b'.', minor])
+                        if major.is_ascii_digit() && minor.is_ascii_digit() =>
+                    {
+                        RequestParseError::UnsupportedVersion
+                    }
+                    _ => RequestParseError::VersionMalformed,
+                }
+            })?,
             None => return Err(RequestParseError::RequestMalformed),
         };
 
+        if tokens.next().is_some() {
+            return Err(RequestParseError::RequestMalformed);
+        }
+
         Ok((method, uri, version))
     }
-}
 
-impl std::string::ToString for Request {
-    fn to_string(&self) -> String {
-        let mut s = String::from(format!(
-            "{} {} {}\n",
-            self.method.to_string(),
-            self.uri.to_string(),
-            self.version.to_string()
-        ));
-        s.push_str(&self.headers.to_string());
+    /// The request line, headers and payload as bytes. A chunked body is
+    /// written the way it was decoded, not re-encoded.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = format!(
+            "{} {} {}\r\n{}",
+            self.method, self.uri, self.version, self.headers
+        )
+        .into_bytes();
+        buf.extend_from_slice(&self.payload);
+
+        buf
+    }
 
-        if let Ok(p) = String::from_utf8(self.payload.clone()) {
-            s.push_str(&p)
+    pub fn write_to<T: Write>(&self, mut s: T) -> Result<usize, std::io::Error> {
+        let buf = self.to_bytes();
+        s.write_all(&buf)?;
+
+        Ok(buf.len())
+    }
+}
+
+/// Reads one line into `line`, replacing its contents. Returns `false` if
+/// there was no line ending within `max` bytes.
+fn read_line<R: BufRead>(
+    reader: &mut R,
+    line: &mut String,
+    max: usize,
+) -> Result<bool, RequestParseError> {
+    line.clear();
+    let n = match reader.by_ref().take(max as u64).read_line(line) {
+        Ok(n) => n,
+        // not UTF-8
+        Err(e) if e.kind() == ErrorKind::InvalidData => {
+            return Err(RequestParseError::RequestMalformed)
         }
+        Err(e) => return Err(e.into()),
+    };
+    if n == 0 {
+        return Err(RequestParseError::Truncated);
+    }
+    if !line.ends_with('\n') {
+        return if n == max {
+            Ok(false)
+        } else {
+            Err(RequestParseError::Truncated)
+        };
+    }
+
+    Ok(true)
+}
 
-        s
+impl std::fmt::Display for Request {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} {} {}", self.method, self.uri, self.version)?;
+        write!(f, "{}", self.headers)?;
+        write!(f, "{}", String::from_utf8_lossy(&self.payload))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::io::Cursor;
+    use std::io::{BufReader, Cursor};
+
+    use super::{Request, RequestParseError};
+    use crate::{HttpVersion, Method};
 
     #[test]
     pub fn test_request() {
@@ -133,13 +469,97 @@ mod tests {
 
         let mut rs = String::new();
         rs.push_str("GET /index.php?param1=2&p2=2 HTTP/1.1\r\n");
-        rs.push_str("Host: testy.mctest.local:8088");
-        rs.push_str("User-Agent: unittest/1.2.3");
-        rs.push_str("X-Test: 1234abc");
-        rs.push_str("Content-Length: 12");
+        rs.push_str("Host: testy.mctest.local:8088\r\n");
+        rs.push_str("User-Agent: unittest/1.2.3\r\n");
+        rs.push_str("X-Test: 1234abc\r\n");
+        rs.push_str("Content-Length: 12\r\n");
         rs.push_str("\r\n");
         rs.push_str("abcdef123456");
 
-        let _c = Cursor::new(rs.as_bytes());
+        let mut c = Cursor::new(rs.as_bytes());
+        let r = Request::new(&mut c).unwrap();
+
+        assert_eq!(r.method, Method::GET);
+        assert_eq!(r.uri.path, "/index.php");
+        assert_eq!(r.version, HttpVersion::HTTP1_1);
+        assert_eq!(
+            r.headers.get("host".to_string()).unwrap(),
+            "testy.mctest.local:8088"
+        );
+        assert_eq!(r.headers.get("x-test".to_string()).unwrap(), "1234abc");
+        assert_eq!(r.payload, b"abcdef123456");
+        assert_eq!(c.position() as usize, rs.len());
+    }
+
+    #[test]
+    fn leftover_bytes() {
+        let raw = b"POST /a HTTP/1.1\r\nHost: localhost\r\nContent-Length: 3\r\n\r\nabcGET /b HTTP/1.1\r\nHost: localhost\r\n\r\n";
+
+        let (r, rest) = Request::parse(raw).unwrap();
+        assert_eq!(r.uri.path, "/a");
+        assert_eq!(r.payload, b"abc");
+        assert_eq!(rest, b"GET /b HTTP/1.1\r\nHost: localhost\r\n\r\n");
+
+        let (r, rest) = Request::parse(rest).unwrap();
+        assert_eq!(r.uri.path, "/b");
+        assert!(rest.is_empty());
+
+        // a small buffer leaves the next request in the reader
+        let mut reader = BufReader::with_capacity(8, &raw[..]);
+        assert_eq!(Request::new(&mut reader).unwrap().uri.path, "/a");
+        assert_eq!(Request::new(&mut reader).unwrap().uri.path, "/b");
+    }
+
+    #[test]
+    fn errors() {
+        let err = |raw: &str| Request::parse(raw.as_bytes()).unwrap_err();
+
+        assert!(matches!(
+            err("GET / HTTP/1.1\r\nHost: localhost\r\nHost: x\r\n"),
+            RequestParseError::Truncated
+        ));
+        assert!(matches!(
+            err("POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\n\r\nab"),
+            RequestParseError::Truncated
+        ));
+        assert!(matches!(
+            err("GET / HTTP/3\r\n\r\n"),
+            RequestParseError::VersionMalformed
+        ));
+        assert!(matches!(
+            err("GET / HTTP/1.1\r\nHost: localhost\r\nHost : x\r\n\r\n"),
+            RequestParseError::RequestMalformed
+        ));
+        // obs-fold, which would hide the Content-Length from us
+        assert!(matches!(
+            err("POST / HTTP/1.1\r\nHost: a\r\n Content-Length: 3\r\n\r\nabc"),
+            RequestParseError::RequestMalformed
+        ));
+        assert!(matches!(
+            err("GET / HTTP/1.1\r\nHost: localhost\r\n\tX: 3\r\n\r\n"),
+            RequestParseError::RequestMalformed
+        ));
+        assert!(matches!(
+            err("GET / HTTP/1.1\r\nHost: localhost\r\nFoo Bar: x\r\n\r\n"),
+            RequestParseError::RequestMalformed
+        ));
+    }
+
+    #[test]
+    fn cookies() {
+        let raw =
+            "GET / HTTP/1.1\r\nHost: localhost\r\nCookie: a=1; b=\"two\"\r\nCookie: a=3\r\n\r\n";
+        let (r, _) = Request::parse(raw.as_bytes()).unwrap();
+
+        assert_eq!(
+            r.cookies(),
+            [
+                ("a".to_string(), "1".to_string()),
+                ("b".to_string(), "two".to_string()),
+                ("a".to_string(), "3".to_string()),
+            ]
+        );
+        assert_eq!(r.cookie("a").as_deref(), Some("1"));
+        assert_eq!(r.cookie("c"), None);
     }
 }
@@ -1,44 +1,6 @@
 use std::io::Write;
 
-use super::{Headers, HttpVersion};
-#[derive(Clone, Copy)]
-pub enum Status {
-    Ok = 200,
-    Created = 201,
-    Accepted = 202,
-    NonAuthoritativeInformation = 203,
-    NoContent = 204,
-    ResetContent = 205,
-    PartialContent = 206,
-
-    NotFound = 404,
-
-    InternalServerError = 500,
-    HttpVersionNotSupported = 505,
-}
-
-impl std::string::ToString for Status {
-    fn to_string(&self) -> String {
-        match self {
-            Self::Ok => format!("{} OK", *self as u16).to_string(),
-            Self::Created => format!("{} Created", *self as u16).to_string(),
-            Self::Accepted => format!("{} Accepted", *self as u16).to_string(),
-            Self::NonAuthoritativeInformation => {
-                format!("{} Non-Authoritative Information", *self as u16).to_string()
-            }
-            Self::NoContent => format!("{} No Content", *self as u16).to_string(),
-            Self::ResetContent => format!("{} Reset Content", *self as u16).to_string(),
-            Self::PartialContent => format!("{} Partial Content", *self as u16).to_string(),
-            Self::NotFound => format!("{} Not Found", *self as u16).to_string(),
-            Self::InternalServerError => {
-                format!("{} Internal Server Error", *self as u16).to_string()
-            }
-            Self::HttpVersionNotSupported => {
-                format!("{} HTTP Version Not Supported", *self as u16).to_string()
-            }
-        }
-    }
-}
+use super::{Cookie, CookieError, Headers, HttpVersion, Status};
 
 pub struct Response {
     version: HttpVersion,
@@ -51,15 +13,43 @@ impl Response {
     pub fn new(status: Status, msg: &str) -> Self {
         Self {
             version: HttpVersion::HTTP1_1,
-            status: status,
+            status,
             headers: Headers::default(),
             payload: msg.bytes().collect(),
         }
     }
 
+    pub fn from_bytes(status: Status, payload: Vec<u8>) -> Self {
+        Self {
+            version: HttpVersion::HTTP1_1,
+            status,
+            headers: Headers::default(),
+            payload,
+        }
+    }
+
+    /// An empty response sending the client to `location`, `status` should be
+    /// one of the redirect codes such as [`Status::SeeOther`] or
+    /// [`Status::PermanentRedirect`].
+    pub fn redirect(status: Status, location: &str) -> Self {
+        let mut resp = Self::new(status, "");
+        resp.headers
+            .add("Location".to_string(), Some(location.to_string()));
+        resp
+    }
+
+    /// Adds a `Set-Cookie` header for `c`, next to the cookies set before.
+    /// Fails if the cookie has a name, value or attribute that can't be sent.
+    pub fn set_cookie(&mut self, c: &Cookie) -> Result<(), CookieError> {
+        c.validate()?;
+        self.headers.append("Set-Cookie", &c.to_string());
+
+        Ok(())
+    }
+
     pub fn set_content_length(&mut self) {
         self.headers.add(
-            "content-length".to_string(),
+            "Content-Length".to_string(),
             Some(self.payload.len().to_string()),
         );
     }
@@ -67,24 +57,104 @@ impl Response {
     pub fn write_to<T: Write>(&mut self, mut s: T) -> Result<usize, std::io::Error> {
         self.set_content_length();
 
-        s.write(self.to_string().as_bytes())
+        let buf = self.to_bytes();
+        s.write_all(&buf)?;
+
+        Ok(buf.len())
+    }
+
+    /// Writes the status line and headers only, for bodies that are streamed
+    /// separately.
+    pub fn write_head_to<T: Write>(&self, mut s: T) -> Result<usize, std::io::Error> {
+        let head = self.head();
+        s.write_all(head.as_bytes())?;
+
+        Ok(head.len())
+    }
+
+    /// The response as sent on the wire. Unlike `to_string` this keeps
+    /// payloads that aren't valid UTF-8 intact.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = self.head().into_bytes();
+        buf.extend_from_slice(&self.payload);
+
+        buf
+    }
+
+    fn head(&self) -> String {
+        format!("{} {}\r\n{}", self.version, self.status, self.headers)
+    }
+}
+
+/// Values a handler can return in place of writing a [`Response`] itself,
+/// see [`Handler::returning`](super::Handler::returning).
+///
+/// Strings and bytes become a `200 OK` body, a [`Status`] an empty response
+/// with that status. Pair either with a status to send it with another one.
+pub trait IntoResponse {
+    fn into_response(self) -> Response;
+}
+
+impl IntoResponse for Response {
+    fn into_response(self) -> Response {
+        self
     }
 }
 
-impl std::string::ToString for Response {
-    fn to_string(&self) -> String {
-        let mut s = String::from(format!(
-            "{} {}\r\n",
-            self.version.to_string(),
-            self.status.to_string()
-        ));
-        s.push_str(&self.headers.to_string());
-
-        if let Ok(p) = String::from_utf8(self.payload.clone()) {
-            s.push_str(&p)
+impl IntoResponse for Status {
+    fn into_response(self) -> Response {
+        Response::new(self, "")
+    }
+}
+
+impl IntoResponse for String {
+    fn into_response(self) -> Response {
+        Response::from_bytes(Status::Ok, self.into_bytes())
+    }
+}
+
+impl IntoResponse for &'static str {
+    fn into_response(self) -> Response {
+        Response::new(Status::Ok, self)
+    }
+}
+
+impl IntoResponse for Vec<u8> {
+    fn into_response(self) -> Response {
+        Response::from_bytes(Status::Ok, self)
+    }
+}
+
+impl<B: IntoResponse> IntoResponse for (Status, B) {
+    fn into_response(self) -> Response {
+        let mut resp = self.1.into_response();
+        resp.status = self.0;
+        resp
+    }
+}
+
+impl<R: IntoResponse, E: IntoResponse> IntoResponse for Result<R, E> {
+    fn into_response(self) -> Response {
+        match self {
+            Ok(r) => r.into_response(),
+            Err(e) => e.into_response(),
         }
+    }
+}
 
-        s
+/// I/O errors raised inside a handler are answered with a plain `500`, the
+/// error itself is only logged.
+impl IntoResponse for std::io::Error {
+    fn into_response(self) -> Response {
+        println!("handler failed: {}", self);
+        Response::new(Status::InternalServerError, "internal server error")
+    }
+}
+
+impl std::fmt::Display for Response {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.head())?;
+        write!(f, "{}", String::from_utf8_lossy(&self.payload))
     }
 }
 
@@ -92,7 +162,7 @@ impl std::string::ToString for Response {
 mod tests {
     use std::io::Cursor;
 
-    use crate::{Headers, HttpVersion, Response, Status};
+    use crate::{Cookie, Headers, HttpVersion, IntoResponse, ParamError, Response, Status};
 
     #[test]
     fn response_write_to() {
@@ -116,7 +186,10 @@ mod tests {
         assert_eq!(resp.headers.content_length().unwrap(), payload_len, "Content-Length is wrong");
        
         let server_header = format!("server: {}\r\n", resp.headers.server().unwrap());
-        let content_len_header =  format!("content-length: {}\r\n", resp.headers.content_length().unwrap());
+        let content_len_header = format!(
+            "Content-Length: {}\r\n",
+            resp.headers.content_length().unwrap()
+        );
 
 
         
@@ -145,17 +218,9 @@ mod tests {
         println!("2 {:?}", header_2);
 
 
-        if header_1.starts_with("server") {
-            assert_eq!(header_1, server_header);
-            assert_eq!(header_2, content_len_header);
-
-        } else if header_2.starts_with("server") {
-            assert_eq!(header_1, content_len_header);
-            assert_eq!(header_2, server_header);
-
-        } else {
-            panic!("no server header")
-        }
+        // headers are sent in the order they were added
+        assert_eq!(header_1, server_header);
+        assert_eq!(header_2, content_len_header);
 
 
 
@@ -171,4 +236,57 @@ mod tests {
 
     
     }
+
+    #[test]
+    fn response_binary_payload() {
+        let payload: Vec<u8> = vec![0x1f, 0x8b, 0x08, 0x00, 0xff, 0xfe, 0x00, 0x80];
+        let mut resp = Response::from_bytes(Status::Ok, payload.clone());
+
+        let mut buf = Vec::new();
+        let n = resp.write_to(&mut buf).unwrap();
+        assert_eq!(n, buf.len());
+        assert!(buf.ends_with(&[b"\r\n\r\n".as_slice(), &payload].concat()));
+        assert_eq!(resp.headers.content_length().unwrap(), payload.len());
+    }
+
+    #[test]
+    fn into_response() {
+        let resp = "hi".into_response();
+        assert_eq!(resp.status.code(), 200);
+        assert_eq!(resp.payload, b"hi");
+
+        let resp = (Status::Created, String::from("made")).into_response();
+        assert_eq!(resp.status.code(), 201);
+        assert_eq!(resp.payload, b"made");
+
+        let resp = (Status::Accepted, vec![0xffu8, 0x00]).into_response();
+        assert_eq!(resp.status.code(), 202);
+        assert_eq!(resp.payload, [0xff, 0x00]);
+
+        let resp = Status::NoContent.into_response();
+        assert_eq!(resp.status.code(), 204);
+        assert!(resp.payload.is_empty());
+
+        let res: Result<String, ParamError> = Err(ParamError::Missing("id".to_string()));
+        let resp = res.into_response();
+        assert_eq!(resp.status.code(), 400);
+        assert_eq!(resp.payload, b"parameter 'id' is missing");
+    }
+
+    #[test]
+    fn set_cookies() {
+        let mut resp = Response::new(Status::Ok, "");
+        resp.set_cookie(&Cookie::new("a", "1").http_only(true))
+            .unwrap();
+        resp.set_cookie(&Cookie::new("b", "2")).unwrap();
+        assert!(resp.set_cookie(&Cookie::new("c", "x;y")).is_err());
+
+        let out = resp.to_string();
+        assert!(
+            out.contains("Set-Cookie: a=1; HttpOnly\r\nSet-Cookie: b=2\r\n"),
+            "{}",
+            out
+        );
+        assert!(!out.contains("c="));
+    }
 }
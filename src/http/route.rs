@@ -0,0 +1,311 @@
+use std::{error::Error, fmt::Display, str::FromStr};
+
+use super::{IntoResponse, Response, Status};
+
+/// A path template handlers are registered for.
+///
+/// Segments are separated by `/` and are either matched literally, captured as
+/// a named parameter (`:id`) or, as the last segment only, capture the rest of
+/// the path (`*rest`):
+///
+/// ```text
+/// /users/:id/posts/:post_id
+/// /static/*rest
+/// ```
+#[derive(Debug, Clone)]
+pub struct Route {
+    template: String,
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Segment {
+    Static(String),
+    Param(String),
+    Wildcard(String),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RouteError {
+    MissingLeadingSlash,
+    EmptyName,
+    DuplicateName(String),
+    WildcardNotLast,
+    /// The route can't be registered next to the existing ones: another
+    /// handler is registered for the same route and method, or a parameter or
+    /// wildcard at the same position has a different name.
+    Conflict(String),
+}
+
+impl Display for RouteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingLeadingSlash => write!(f, "route has to start with '/'"),
+            Self::EmptyName => write!(f, "parameter or wildcard without a name"),
+            Self::DuplicateName(n) => write!(f, "parameter '{}' is used more than once", n),
+            Self::WildcardNotLast => write!(f, "wildcard has to be the last segment"),
+            Self::Conflict(r) => write!(f, "route '{}' conflicts with a registered route", r),
+        }
+    }
+}
+
+impl Error for RouteError {}
+
+impl Route {
+    pub fn new(template: &str) -> Result<Self, RouteError> {
+        let Some(rest) = template.strip_prefix('/') else {
+            return Err(RouteError::MissingLeadingSlash);
+        };
+
+        let mut segments = vec![];
+        let mut names: Vec<&str> = vec![];
+        for s in rest.split('/') {
+            if matches!(segments.last(), Some(Segment::Wildcard(_))) {
+                return Err(RouteError::WildcardNotLast);
+            }
+
+            let (name, segment) = if let Some(n) = s.strip_prefix(':') {
+                (n, Segment::Param(n.to_string()))
+            } else if let Some(n) = s.strip_prefix('*') {
+                (n, Segment::Wildcard(n.to_string()))
+            } else {
+                segments.push(Segment::Static(s.to_string()));
+                continue;
+            };
+
+            if name.is_empty() {
+                return Err(RouteError::EmptyName);
+            }
+            if names.contains(&name) {
+                return Err(RouteError::DuplicateName(name.to_string()));
+            }
+            names.push(name);
+            segments.push(segment);
+        }
+
+        Ok(Self {
+            template: template.to_string(),
+            segments,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.template
+    }
+
+    pub(crate) fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    pub(crate) fn has_wildcard(&self) -> bool {
+        matches!(self.segments.last(), Some(Segment::Wildcard(_)))
+    }
+
+    /// Appends `other` to this route, e.g. `/api` and `/users/:id` become
+    /// `/api/users/:id`.
+    pub(crate) fn join(&self, other: &Route) -> Result<Route, RouteError> {
+        let prefix = self.template.trim_end_matches('/');
+        match other.as_str() {
+            "/" if !prefix.is_empty() => Route::new(prefix),
+            t => Route::new(&format!("{}{}", prefix, t)),
+        }
+    }
+
+    /// Matches `path` against the template and returns the captured
+    /// parameters, or `None` if it doesn't match.
+    pub fn matches(&self, path: &str) -> Option<Params> {
+        let mut params = Params::default();
+        let mut rest = Some(path.strip_prefix('/')?);
+
+        for segment in &self.segments {
+            // None once the path has no segments left
+            let r = rest?;
+            if let Segment::Wildcard(name) = segment {
+                params.push(name, r);
+                return Some(params);
+            }
+
+            let (part, tail) = match r.split_once('/') {
+                Some((p, t)) => (p, Some(t)),
+                None => (r, None),
+            };
+            match segment {
+                Segment::Static(s) if s != part => return None,
+                Segment::Param(_) if part.is_empty() => return None,
+                Segment::Param(name) => params.push(name, part),
+                _ => {}
+            }
+            rest = tail;
+        }
+
+        rest.is_none().then_some(params)
+    }
+}
+
+impl FromStr for Route {
+    type Err = RouteError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s)
+    }
+}
+
+impl Display for Route {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.template)
+    }
+}
+
+/// Path parameters captured by a [`Route`], in the order they appear.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Params {
+    params: Vec<(String, String)>,
+}
+
+impl Params {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.params.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.params.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.params.len()
+    }
+
+    pub(crate) fn push(&mut self, name: &str, value: &str) {
+        self.params.push((name.to_string(), value.to_string()));
+    }
+
+    pub(crate) fn truncate(&mut self, len: usize) {
+        self.params.truncate(len);
+    }
+}
+
+/// A path or query parameter that is missing or couldn't be parsed. Converts
+/// into a `400 Bad Request` response.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParamError {
+    Missing(String),
+    Invalid {
+        name: String,
+        value: String,
+    },
+    /// The query doesn't have the shape of the type it was deserialized into.
+    Query(String),
+}
+
+/// Parses `value`, the value of the parameter `name` if there is one, for the
+/// typed accessors of path and query parameters.
+pub(crate) fn parse_param<T: FromStr>(name: &str, value: Option<&str>) -> Result<T, ParamError> {
+    let v = value.ok_or_else(|| ParamError::Missing(name.to_string()))?;
+
+    v.parse().map_err(|_| ParamError::Invalid {
+        name: name.to_string(),
+        value: v.to_string(),
+    })
+}
+
+impl Display for ParamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing(n) => write!(f, "parameter '{}' is missing", n),
+            Self::Invalid { name, value } => {
+                write!(f, "parameter '{}' has an invalid value '{}'", name, value)
+            }
+            Self::Query(e) => write!(f, "invalid query: {}", e),
+        }
+    }
+}
+
+impl Error for ParamError {}
+
+impl From<ParamError> for Response {
+    fn from(e: ParamError) -> Self {
+        Response::new(Status::BadRequest, &e.to_string())
+    }
+}
+
+impl IntoResponse for ParamError {
+    fn into_response(self) -> Response {
+        self.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Route, RouteError};
+
+    fn params(route: &str, path: &str) -> Option<Vec<(String, String)>> {
+        Route::new(route).unwrap().matches(path).map(|p| {
+            p.iter()
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+                .collect()
+        })
+    }
+
+    fn p(pairs: &[(&str, &str)]) -> Option<Vec<(String, String)>> {
+        Some(
+            pairs
+                .iter()
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn static_routes() {
+        assert_eq!(params("/", "/"), p(&[]));
+        assert_eq!(params("/a/b", "/a/b"), p(&[]));
+        assert_eq!(params("/a/b", "/a/b/"), None);
+        assert_eq!(params("/a/b", "/a"), None);
+        assert_eq!(params("/a", "a"), None);
+    }
+
+    #[test]
+    fn params_and_wildcards() {
+        assert_eq!(
+            params("/users/:id/posts/:post_id", "/users/7/posts/abc"),
+            p(&[("id", "7"), ("post_id", "abc")])
+        );
+        assert_eq!(params("/users/:id", "/users/"), None);
+        assert_eq!(params("/users/:id", "/users/7/x"), None);
+
+        assert_eq!(
+            params("/static/*rest", "/static/css/site.css"),
+            p(&[("rest", "css/site.css")])
+        );
+        assert_eq!(params("/static/*rest", "/static/"), p(&[("rest", "")]));
+        assert_eq!(params("/static/*rest", "/static"), None);
+        assert_eq!(
+            params("/:a/*rest", "/x/y/z"),
+            p(&[("a", "x"), ("rest", "y/z")])
+        );
+    }
+
+    #[test]
+    fn invalid_templates() {
+        assert_eq!(
+            Route::new("a").unwrap_err(),
+            RouteError::MissingLeadingSlash
+        );
+        assert_eq!(Route::new("/a/:").unwrap_err(), RouteError::EmptyName);
+        assert_eq!(
+            Route::new("/*a/b").unwrap_err(),
+            RouteError::WildcardNotLast
+        );
+        assert_eq!(
+            Route::new("/:a/:a").unwrap_err(),
+            RouteError::DuplicateName("a".to_string())
+        );
+    }
+}
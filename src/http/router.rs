@@ -0,0 +1,223 @@
+use std::{collections::HashMap, io::Write};
+
+use super::{
+    handlers::Handler,
+    route::{Params, RouteError, Segment},
+    Method,
+};
+
+/// A prefix tree over path segments holding the registered handlers.
+///
+/// Looking up a path walks one node per segment, so the cost depends on the
+/// length of the path and not on the number of routes. Where several routes
+/// match, static segments win over parameters, which win over wildcards.
+pub(crate) struct Node<T: Write> {
+    statics: HashMap<String, Node<T>>,
+    param: Option<(String, Box<Node<T>>)>,
+    wildcard: Option<(String, Vec<Handler<T>>)>,
+    handlers: Vec<Handler<T>>,
+}
+
+impl<T: Write> Default for Node<T> {
+    fn default() -> Self {
+        Self {
+            statics: HashMap::new(),
+            param: None,
+            wildcard: None,
+            handlers: vec![],
+        }
+    }
+}
+
+impl<T: Write> Node<T> {
+    /// Adds a handler for its route. Fails if the route would capture a
+    /// parameter under a different name than an existing route at the same
+    /// position, or if another handler is registered for the same route and
+    /// one of the same methods.
+    pub fn insert(&mut self, h: Handler<T>) -> Result<(), RouteError> {
+        let route = h.pattern.clone();
+        let mut node = self;
+
+        for segment in route.segments() {
+            node = match segment {
+                Segment::Static(s) => node.statics.entry(s.clone()).or_default(),
+                Segment::Param(name) => {
+                    let (existing, child) = node
+                        .param
+                        .get_or_insert_with(|| (name.clone(), Box::default()));
+                    if existing != name {
+                        return Err(RouteError::Conflict(route.to_string()));
+                    }
+                    child
+                }
+                Segment::Wildcard(name) => {
+                    let (existing, handlers) =
+                        node.wildcard.get_or_insert_with(|| (name.clone(), vec![]));
+                    if existing != name {
+                        return Err(RouteError::Conflict(route.to_string()));
+                    }
+                    return Self::add(handlers, h);
+                }
+            };
+        }
+
+        Self::add(&mut node.handlers, h)
+    }
+
+    fn add(handlers: &mut Vec<Handler<T>>, h: Handler<T>) -> Result<(), RouteError> {
+        let overlaps = |m: &Method| handlers.iter().any(|e| e.methods.contains(m));
+        if h.methods.iter().any(overlaps) {
+            return Err(RouteError::Conflict(h.pattern.to_string()));
+        }
+        handlers.push(h);
+
+        Ok(())
+    }
+
+    /// Finds the handlers of the most specific route matching `path`, adding
+    /// the parameters it captures to `params`.
+    pub fn lookup(&self, path: &str, params: &mut Params) -> Option<&[Handler<T>]> {
+        self.find(Some(path.strip_prefix('/')?), params)
+    }
+
+    /// Every registered handler, in no particular order.
+    pub fn all(&self) -> Vec<&Handler<T>> {
+        let mut all: Vec<&Handler<T>> = self.handlers.iter().collect();
+        all.extend(self.statics.values().flat_map(|n| n.all()));
+        if let Some((_, child)) = &self.param {
+            all.extend(child.all());
+        }
+        if let Some((_, handlers)) = &self.wildcard {
+            all.extend(handlers);
+        }
+
+        all
+    }
+
+    fn find(&self, rest: Option<&str>, params: &mut Params) -> Option<&[Handler<T>]> {
+        // None once the path has no segments left
+        let Some(r) = rest else {
+            return (!self.handlers.is_empty()).then_some(self.handlers.as_slice());
+        };
+
+        let (part, tail) = match r.split_once('/') {
+            Some((p, t)) => (p, Some(t)),
+            None => (r, None),
+        };
+
+        if let Some(child) = self.statics.get(part) {
+            if let Some(h) = child.find(tail, params) {
+                return Some(h);
+            }
+        }
+
+        if let Some((name, child)) = &self.param {
+            if !part.is_empty() {
+                let len = params.len();
+                params.push(name, part);
+                if let Some(h) = child.find(tail, params) {
+                    return Some(h);
+                }
+                // backtrack, the parameter didn't lead to a route
+                params.truncate(len);
+            }
+        }
+
+        if let Some((name, handlers)) = &self.wildcard {
+            params.push(name, r);
+            return Some(handlers);
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Node;
+    use crate::{Handler, Method, Params, Response, Route, RouteError, Status};
+
+    fn handler(methods: Vec<Method>, route: &str) -> Handler<Vec<u8>> {
+        let name = route.to_string();
+        Handler::new(methods, Route::new(route).unwrap(), move |_, w| {
+            w.send(&mut Response::new(Status::Ok, &name))
+        })
+    }
+
+    fn router(routes: &[&str]) -> Node<Vec<u8>> {
+        let mut n = Node::default();
+        for r in routes {
+            n.insert(handler(vec![Method::GET], r)).unwrap();
+        }
+
+        n
+    }
+
+    /// The route that handles `path` and the parameters it captured.
+    fn lookup(n: &Node<Vec<u8>>, path: &str) -> Option<(String, Vec<(String, String)>)> {
+        let mut params = Params::default();
+        let h = n.lookup(path, &mut params)?;
+        let params = params
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+
+        Some((h[0].pattern.to_string(), params))
+    }
+
+    #[test]
+    fn precedence() {
+        let n = router(&["/users/me", "/users/:id", "/users/*rest", "/"]);
+
+        assert_eq!(lookup(&n, "/users/me").unwrap().0, "/users/me");
+        assert_eq!(
+            lookup(&n, "/users/7"),
+            Some(("/users/:id".to_string(), vec![("id".into(), "7".into())]))
+        );
+        assert_eq!(lookup(&n, "/users/7/x").unwrap().0, "/users/*rest");
+        assert_eq!(lookup(&n, "/users/").unwrap().0, "/users/*rest");
+        assert_eq!(lookup(&n, "/").unwrap().0, "/");
+        assert_eq!(lookup(&n, "/users"), None);
+        assert_eq!(lookup(&n, "/other"), None);
+    }
+
+    #[test]
+    fn backtracking() {
+        let n = router(&["/a/b/c", "/a/:x/d", "/*rest"]);
+
+        assert_eq!(lookup(&n, "/a/b/c").unwrap().0, "/a/b/c");
+        assert_eq!(
+            lookup(&n, "/a/b/d"),
+            Some(("/a/:x/d".to_string(), vec![("x".into(), "b".into())]))
+        );
+        assert_eq!(
+            lookup(&n, "/a/b"),
+            Some(("/*rest".to_string(), vec![("rest".into(), "a/b".into())]))
+        );
+    }
+
+    #[test]
+    fn conflicts() {
+        let mut n = router(&["/users/:id", "/files/*path"]);
+
+        let conflict = |r: &str| Err(RouteError::Conflict(r.to_string()));
+        assert_eq!(
+            n.insert(handler(vec![Method::GET], "/users/:name")),
+            conflict("/users/:name")
+        );
+        assert_eq!(
+            n.insert(handler(vec![Method::GET], "/files/*rest")),
+            conflict("/files/*rest")
+        );
+        assert_eq!(
+            n.insert(handler(vec![Method::POST, Method::GET], "/users/:id")),
+            conflict("/users/:id")
+        );
+
+        // same route with other methods is fine
+        assert!(n.insert(handler(vec![Method::POST], "/users/:id")).is_ok());
+        assert!(n
+            .insert(handler(vec![Method::GET], "/users/:id/posts"))
+            .is_ok());
+    }
+}
@@ -1,11 +1,95 @@
-use crate::http::{Handlers, HttpVersion, Request, Response, Status};
-use std::net::{TcpListener, TcpStream};
-use std::process::exit;
-use std::time::Duration;
+use crate::http::deadline::DeadlineStream;
+use crate::http::pool::{OverloadPolicy, WorkerPool};
+use crate::http::vhost::{HostPattern, HostPatternError, VirtualHosts};
+use crate::http::{
+    Handlers, HttpVersion, Limits, Method, Middleware, Next, Request, RequestParseError, Response,
+    ResponseWriter, ShutdownHandle, Status,
+};
+use std::any::Any;
+use std::error::Error;
+use std::fmt::Display;
+use std::io::{BufRead, BufReader, ErrorKind, Read};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Most bytes read and discarded from a connection closed after an error.
+const MAX_LINGER_BYTES: u64 = 1024 * 1024;
+
+pub struct Config {
+    /// Number of worker threads handling connections.
+    pub workers: usize,
+    /// Number of accepted connections that may wait for a free worker.
+    pub queue_depth: usize,
+    /// What to do with new connections once the queue is full.
+    pub overload: OverloadPolicy,
+    /// How long a kept-alive connection may wait for its next request.
+    pub idle_timeout: Duration,
+    /// Number of requests served on one connection before it is closed.
+    pub max_requests_per_connection: usize,
+    /// How long `run` waits for in-flight requests after a shutdown was requested.
+    pub shutdown_timeout: Duration,
+    /// How long a client may take to send a request, from its first byte to
+    /// the end of its body.
+    pub request_timeout: Duration,
+    /// Bounds on the size of each request.
+    pub limits: Limits,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            workers: thread::available_parallelism().map_or(4, |n| n.get()),
+            queue_depth: 64,
+            overload: OverloadPolicy::Reject,
+            idle_timeout: Duration::new(5, 0),
+            max_requests_per_connection: 100,
+            shutdown_timeout: Duration::new(30, 0),
+            request_timeout: Duration::new(30, 0),
+            limits: Limits::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ServerError {
+    Bind {
+        addr: String,
+        source: std::io::Error,
+    },
+    LocalAddr(std::io::Error),
+    SpawnWorker(std::io::Error),
+}
+
+impl Display for ServerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Bind { addr, source } => write!(f, "couldn't bind to {}: {}", addr, source),
+            Self::LocalAddr(e) => write!(f, "couldn't determine local address: {}", e),
+            Self::SpawnWorker(e) => write!(f, "couldn't spawn worker thread: {}", e),
+        }
+    }
+}
+
+impl Error for ServerError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Bind { source, .. } => Some(source),
+            Self::LocalAddr(e) | Self::SpawnWorker(e) => Some(e),
+        }
+    }
+}
 
 pub struct Server {
     addr: String,
+    /// The handlers for requests that don't match any virtual host.
     pub handlers: Handlers<TcpStream>,
+    vhosts: VirtualHosts<TcpStream>,
+    pub config: Config,
+    shutdown: ShutdownHandle,
+    state: Option<Arc<dyn Any + Send + Sync>>,
+    middleware: Vec<Arc<dyn Middleware<TcpStream>>>,
 }
 
 impl Server {
@@ -13,61 +97,197 @@ impl Server {
         Self {
             addr,
             handlers: Handlers::new(),
+            vhosts: VirtualHosts::default(),
+            config: Config::default(),
+            shutdown: ShutdownHandle::new(),
+            state: None,
+            middleware: vec![],
         }
     }
 
-    fn dispatch_request(&self, r: Request, mut w: TcpStream) -> bool {
-        let (found, handler) = self.handlers.get(&r.method, &r.uri.path);
-        handler.handle(r, &mut w).unwrap();
+    /// Sets the application state every handler can reach through
+    /// [`Request::state`], e.g. a connection pool or configuration. Use types
+    /// with interior mutability for anything handlers change.
+    pub fn set_state<S: Any + Send + Sync>(&mut self, state: S) {
+        self.state = Some(Arc::new(state));
+    }
 
-        return found;
+    /// The handlers for requests to hosts matching `pattern`, such as
+    /// `api.local` or `*.api.local`, see [`HostPattern`]. The host is taken
+    /// from an absolute-form target or the `Host` header; requests for other
+    /// hosts go to [`Server::handlers`].
+    ///
+    /// ```
+    /// # use server::{Handler, Method, Route, Server};
+    /// let mut server = Server::new("localhost:8080".to_string());
+    /// server
+    ///     .host("*.api.local")
+    ///     .unwrap()
+    ///     .register(Handler::returning(vec![Method::GET], Route::new("/").unwrap(), |_| "api"))
+    ///     .unwrap();
+    /// ```
+    pub fn host(&mut self, pattern: &str) -> Result<&mut Handlers<TcpStream>, HostPatternError> {
+        Ok(self.vhosts.get_or_insert(HostPattern::new(pattern)?))
     }
 
-    pub fn run(self) {
-        println!("binding to {}", self.addr);
-        let listener = match TcpListener::bind(&self.addr) {
-            Ok(l) => l,
-            Err(error) => {
-                println!("couldn't bind to {}: {}", self.addr, error.to_string());
-                exit(1);
-            }
-        };
-        println!("running on {}", self.addr);
+    /// Adds a middleware that runs for every request, before the handler is
+    /// looked up, after the ones added before it.
+    pub fn wrap<M: Middleware<TcpStream> + 'static>(&mut self, m: M) {
+        self.middleware.push(Arc::new(m));
+    }
+
+    /// Returns a handle that makes `serve` stop accepting connections, drain
+    /// the ones in progress and return.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    fn dispatch_request(
+        &self,
+        mut r: Request,
+        w: &mut ResponseWriter<TcpStream>,
+    ) -> Result<usize, std::io::Error> {
+        r.state = self.state.clone();
+
+        Next::new(&self.middleware, &|r, w| {
+            let handlers = r.host().and_then(|h| self.vhosts.select(&h));
+            handlers.unwrap_or(&self.handlers).handle(r, w)
+        })
+        .run(r, w)
+    }
+
+    fn handle_connection(&self, stream: TcpStream) -> Result<(), std::io::Error> {
+        stream.set_write_timeout(Some(Duration::new(5, 0)))?;
+
+        let mut reader = BufReader::new(DeadlineStream::new(stream.try_clone()?));
+        let mut w = ResponseWriter::new(stream, HttpVersion::default(), true);
+        let mut served = 0;
+
         loop {
-            let (stream, remote_addr) = match listener.accept() {
-                Ok((stream, addr)) => (stream, addr),
+            if !self.wait_for_request(&mut reader, served > 0) {
+                return Ok(());
+            }
+
+            let deadline = Instant::now() + self.config.request_timeout;
+            reader.get_mut().set_deadline(Some(deadline));
+            let req = Request::with_limits(&mut reader, &self.config.limits);
+            reader.get_mut().set_deadline(None);
+
+            let req = match req {
+                Ok(req) => req,
                 Err(e) => {
-                    println!("failed to accept connection: {}", e.to_string());
-                    continue;
+                    println!("failed to parse request: {}", e);
+                    return Self::refuse(&mut w, &mut reader, e);
                 }
             };
-            stream.set_write_timeout(Some(Duration::new(5, 0))).unwrap();
-            stream.set_read_timeout(Some(Duration::new(5, 0))).unwrap();
+            served += 1;
+            let keep_alive = req.keep_alive()
+                && served < self.config.max_requests_per_connection
+                && !self.shutdown.is_shutdown();
+            println!("{} {} {}", req.method, req.uri, req.version);
+            w.start(req.method, req.version, keep_alive);
 
-            println!("new connection from {:?}", remote_addr.ip());
-            let Ok(req) = Request::new(&stream) else {
-                println!("failed to parse request");
-                continue;
-            };
-            if req.version != HttpVersion::HTTP1_1 {
-                let mut resp = Response::new(
-                    Status::HttpVersionNotSupported,
-                    "HTTP version is not supported",
-                );
-                resp.write_to(stream).unwrap();
-                continue;
+            self.dispatch_request(req, &mut w)?;
+
+            if !w.keep_alive() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Waits until the next request starts arriving. Returns `false` if the
+    /// connection should be closed instead because the client went away, it
+    /// was idle for too long or, for a kept-alive connection that already
+    /// served a request, the server is shutting down. The first request of a
+    /// connection is still waited for during a shutdown, as the client sent it
+    /// before the connection was accepted. Pipelined requests are already in
+    /// the buffer.
+    fn wait_for_request(&self, reader: &mut BufReader<DeadlineStream>, kept_alive: bool) -> bool {
+        let deadline = Instant::now() + self.config.idle_timeout;
+        loop {
+            if !reader.buffer().is_empty() {
+                return true;
+            }
+            if kept_alive && self.shutdown.is_shutdown() {
+                return false;
             }
 
-            println!("************");
-            println!("{}", req.to_string());
-            println!("************");
+            // wait in short slices to notice a shutdown while idle
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return false;
+            }
+            let slice = left.min(Duration::from_millis(100));
+            if reader
+                .get_ref()
+                .get_ref()
+                .set_read_timeout(Some(slice))
+                .is_err()
+            {
+                return false;
+            }
 
-            if !self.dispatch_request(req, stream) {
-                println!("Couldn´t dispatch request");
-            };
+            match reader.fill_buf() {
+                Ok(b) => return !b.is_empty(),
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(_) => return false,
+            }
         }
     }
 
+    /// Answers a request that couldn't be parsed and closes the connection,
+    /// the rest of the stream can't be trusted to start with a request.
+    fn refuse(
+        w: &mut ResponseWriter<TcpStream>,
+        reader: &mut BufReader<DeadlineStream>,
+        e: RequestParseError,
+    ) -> Result<(), std::io::Error> {
+        let Some(status) = e.status() else {
+            return Ok(());
+        };
+        w.start(Method::GET, HttpVersion::default(), false);
+        w.send(&mut Response::new(status, &e.to_string()))?;
+
+        // closing with unread data resets the connection, which can make the
+        // client lose the response, so drain what it is still sending first
+        w.get_ref().shutdown(Shutdown::Write)?;
+        let deadline = Instant::now() + Duration::from_secs(1);
+        reader.get_mut().set_deadline(Some(deadline));
+        let _ = std::io::copy(&mut reader.take(MAX_LINGER_BYTES), &mut std::io::sink());
+
+        Ok(())
+    }
+
+    fn reject(stream: TcpStream) {
+        let _ = stream.set_write_timeout(Some(Duration::new(1, 0)));
+        let mut resp = Response::new(Status::ServiceUnavailable, "server is busy");
+        resp.headers.insert("Connection", "close");
+        if let Err(e) = resp.write_to(stream) {
+            println!("failed to reject connection: {}", e);
+        }
+    }
+
+    /// Binds the listening socket without accepting connections yet.
+    pub fn bind(self) -> Result<BoundServer, ServerError> {
+        println!("binding to {}", self.addr);
+        let listener = TcpListener::bind(&self.addr).map_err(|source| ServerError::Bind {
+            addr: self.addr.clone(),
+            source,
+        })?;
+        let local_addr = listener.local_addr().map_err(ServerError::LocalAddr)?;
+        self.shutdown.listening_on(local_addr);
+
+        Ok(BoundServer {
+            server: self,
+            listener,
+            local_addr,
+        })
+    }
+
+    /// Binds and serves until a shutdown is requested.
+    pub fn run(self) -> Result<(), ServerError> {
+        self.bind()?.serve()
+    }
     // fn read_request(stream: &TcpStream) -> Result<Request, ()> {
     //     let mut line_buf = String::new();
     //     let mut reader = BufReader::new(stream);
@@ -112,3 +332,856 @@ impl Server {
     //     Ok(Request {method, uri, headers, version, payload})
     // }
 }
+
+/// A [`Server`] whose socket is bound but that doesn't accept connections yet.
+pub struct BoundServer {
+    server: Server,
+    listener: TcpListener,
+    local_addr: SocketAddr,
+}
+
+impl BoundServer {
+    /// The address the server listens on, with the actual port if it was
+    /// bound to port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.server.shutdown_handle()
+    }
+
+    /// Accepts and serves connections until a shutdown is requested, then
+    /// waits for in-flight requests up to the configured shutdown timeout.
+    pub fn serve(self) -> Result<(), ServerError> {
+        let Self {
+            server,
+            listener,
+            local_addr,
+        } = self;
+        println!("running on {}", local_addr);
+
+        let (workers, queue_depth, overload) = (
+            server.config.workers,
+            server.config.queue_depth,
+            server.config.overload,
+        );
+        let shutdown = server.shutdown.clone();
+        let shutdown_timeout = server.config.shutdown_timeout;
+        let server = Arc::new(server);
+        let pool = WorkerPool::new(workers, queue_depth, overload, move |stream| {
+            let peer = stream.peer_addr();
+            if let Err(e) = server.handle_connection(stream) {
+                println!("connection from {:?} failed: {}", peer, e);
+            }
+        })
+        .map_err(ServerError::SpawnWorker)?;
+
+        while !shutdown.is_shutdown() {
+            let (stream, remote_addr) = match listener.accept() {
+                Ok(_) if shutdown.is_shutdown() => break,
+                Ok((stream, addr)) => (stream, addr),
+                Err(e) => {
+                    println!("failed to accept connection: {}", e);
+                    continue;
+                }
+            };
+
+            println!("new connection from {:?}", remote_addr.ip());
+            if let Err(stream) = pool.submit(stream) {
+                println!("all workers busy, rejecting {:?}", remote_addr.ip());
+                Server::reject(stream);
+            }
+        }
+
+        println!("shutting down, waiting for in-flight requests");
+        drop(listener);
+        if !pool.shutdown(shutdown_timeout) {
+            println!("shutdown timeout reached, abandoning remaining connections");
+        }
+        println!("stopped");
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::{Shutdown, SocketAddr, TcpStream},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            mpsc, Arc, Mutex,
+        },
+        thread::{self, JoinHandle},
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        Group, Handler, Limits, Method, Middleware, Next, ParamError, Request, Response,
+        ResponseWriter, Route, Server, ServerError, ShutdownHandle, Status,
+    };
+
+    fn start(
+        server: Server,
+    ) -> (
+        SocketAddr,
+        ShutdownHandle,
+        JoinHandle<Result<(), ServerError>>,
+    ) {
+        let bound = server.bind().unwrap();
+        let addr = bound.local_addr();
+        let shutdown = bound.shutdown_handle();
+
+        (addr, shutdown, thread::spawn(move || bound.serve()))
+    }
+
+    /// Sends `raw` on a new connection and reads until the server closes it.
+    fn roundtrip(addr: SocketAddr, raw: &str) -> String {
+        let mut s = TcpStream::connect(addr).unwrap();
+        s.write_all(raw.as_bytes()).unwrap();
+        let mut resp = String::new();
+        s.read_to_string(&mut resp).unwrap();
+
+        resp
+    }
+
+    #[test]
+    fn serve_and_shutdown() {
+        let (addr, shutdown, t) = start(Server::new("127.0.0.1:0".to_string()));
+        assert_ne!(addr.port(), 0);
+
+        let resp = roundtrip(
+            addr,
+            "GET /missing HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        );
+        assert!(resp.starts_with("HTTP/1.1 404 Not Found\r\n"), "{}", resp);
+
+        let resp = roundtrip(
+            addr,
+            "OPTIONS * HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        );
+        assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"), "{}", resp);
+        let resp = roundtrip(
+            addr,
+            "GET * HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        );
+        assert!(resp.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{}", resp);
+        let resp = roundtrip(
+            addr,
+            "CONNECT /a HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        );
+        assert!(resp.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{}", resp);
+
+        shutdown.shutdown();
+        assert!(t.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn keep_alive_and_pipelining() {
+        let mut server = Server::new("127.0.0.1:0".to_string());
+        server.config.max_requests_per_connection = 3;
+        server.config.idle_timeout = Duration::from_millis(200);
+        server
+            .handlers
+            .register(Handler::returning(
+                vec![Method::GET],
+                Route::new("/*path").unwrap(),
+                |r| r.uri.path.clone(),
+            ))
+            .unwrap();
+        let (addr, shutdown, t) = start(server);
+
+        // all requests in one write, answered in order on the same connection
+        // until the last one allowed
+        let resp = roundtrip(
+            addr,
+            &["/a", "/b", "/c"]
+                .map(|p| format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", p))
+                .concat(),
+        );
+        let answers: Vec<&str> = resp.split("HTTP/1.1 200 OK\r\n").skip(1).collect();
+        assert_eq!(answers.len(), 3, "{}", resp);
+        for (a, path) in answers.iter().zip(["/a", "/b", "/c"]) {
+            assert!(a.ends_with(&format!("\r\n\r\n{}", path)), "{}", resp);
+        }
+        assert!(!answers[0].contains("Connection:"), "{}", resp);
+        assert!(!answers[1].contains("Connection:"), "{}", resp);
+        assert!(answers[2].contains("Connection: close\r\n"), "{}", resp);
+
+        // an idle connection is closed after the idle timeout
+        let start = Instant::now();
+        let resp = roundtrip(addr, "GET /a HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"), "{}", resp);
+        assert!(!resp.contains("Connection:"), "{}", resp);
+        let elapsed = start.elapsed();
+        assert!(
+            elapsed >= Duration::from_millis(200) && elapsed < Duration::from_secs(2),
+            "{:?}",
+            elapsed
+        );
+
+        shutdown.shutdown();
+        t.join().unwrap().unwrap();
+    }
+
+    /// A server whose only handler sleeps for `delay`, and a receiver that gets
+    /// a message as soon as the handler starts.
+    fn slow_server(delay: Duration, shutdown_timeout: Duration) -> (Server, mpsc::Receiver<()>) {
+        let (started_tx, started_rx) = mpsc::channel();
+        let started_tx = Mutex::new(started_tx);
+
+        let mut server = Server::new("127.0.0.1:0".to_string());
+        server.config.shutdown_timeout = shutdown_timeout;
+        server
+            .handlers
+            .register(Handler::returning(
+                vec![Method::GET],
+                Route::new("/slow").unwrap(),
+                move |_| {
+                    started_tx.lock().unwrap().send(()).unwrap();
+                    thread::sleep(delay);
+                    "done"
+                },
+            ))
+            .unwrap();
+
+        (server, started_rx)
+    }
+
+    #[test]
+    fn shutdown_drains_requests() {
+        let (server, started) = slow_server(Duration::from_millis(300), Duration::from_secs(5));
+        let (addr, shutdown, t) = start(server);
+
+        let client = thread::spawn(move || {
+            roundtrip(
+                addr,
+                "GET /slow HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+            )
+        });
+        started.recv().unwrap();
+        shutdown.shutdown();
+
+        // the request in progress is finished before serve returns
+        t.join().unwrap().unwrap();
+        let resp = client.join().unwrap();
+        assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"), "{}", resp);
+        assert!(resp.ends_with("\r\n\r\ndone"), "{}", resp);
+    }
+
+    #[test]
+    fn shutdown_serves_queued_connections() {
+        let (mut server, started) = slow_server(Duration::from_millis(300), Duration::from_secs(5));
+        server.config.workers = 1;
+        let (addr, shutdown, t) = start(server);
+
+        let request = "GET /slow HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+        let running = thread::spawn(move || roundtrip(addr, request));
+        started.recv().unwrap();
+
+        // sent completely while the only worker is busy, so it waits in the queue
+        let mut queued = TcpStream::connect(addr).unwrap();
+        queued.write_all(request.as_bytes()).unwrap();
+        thread::sleep(Duration::from_millis(50));
+        shutdown.shutdown();
+
+        let mut resp = String::new();
+        queued.read_to_string(&mut resp).unwrap();
+        assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"), "{}", resp);
+        assert!(resp.ends_with("\r\n\r\ndone"), "{}", resp);
+        assert!(running.join().unwrap().ends_with("\r\n\r\ndone"));
+        t.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn shutdown_timeout() {
+        let (server, started) = slow_server(Duration::from_secs(2), Duration::from_millis(100));
+        let (addr, shutdown, t) = start(server);
+
+        thread::spawn(move || {
+            roundtrip(
+                addr,
+                "GET /slow HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+            )
+        });
+        started.recv().unwrap();
+
+        // serve gives up on the request once the timeout has passed
+        let start = Instant::now();
+        shutdown.shutdown();
+        t.join().unwrap().unwrap();
+        assert!(
+            start.elapsed() < Duration::from_secs(1),
+            "{:?}",
+            start.elapsed()
+        );
+    }
+
+    #[test]
+    fn closure_handler_and_state() {
+        let mut server = Server::new("127.0.0.1:0".to_string());
+        server.set_state(String::from("from state"));
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        server
+            .handlers
+            .register(Handler::new(
+                vec![Method::GET],
+                Route::new("/state").unwrap(),
+                move |r, w| {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    let msg = r.state::<String>().unwrap().clone();
+                    assert!(r.state::<u32>().is_none());
+
+                    w.send(&mut Response::new(Status::Ok, &msg))
+                },
+            ))
+            .unwrap();
+
+        let (addr, shutdown, t) = start(server);
+        let resp = roundtrip(
+            addr,
+            "GET /state HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        );
+        assert!(resp.ends_with("\r\n\r\nfrom state"), "{}", resp);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        shutdown.shutdown();
+        t.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn path_params() {
+        let mut server = Server::new("127.0.0.1:0".to_string());
+        server
+            .handlers
+            .register(Handler::new(
+                vec![Method::GET],
+                Route::new("/users/:id/*rest").unwrap(),
+                |r, w| {
+                    let id = match r.param_as::<u32>("id") {
+                        Ok(id) => id,
+                        Err(e) => return w.send(&mut e.into()),
+                    };
+                    let msg = format!("{} {}", id, r.param("rest").unwrap());
+
+                    w.send(&mut Response::new(Status::Ok, &msg))
+                },
+            ))
+            .unwrap();
+
+        let (addr, shutdown, t) = start(server);
+        let resp = roundtrip(
+            addr,
+            "GET /users/42/a/b HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        );
+        assert!(resp.ends_with("\r\n\r\n42 a/b"), "{}", resp);
+        let resp = roundtrip(
+            addr,
+            "GET /users/x/ HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        );
+        assert!(resp.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{}", resp);
+
+        // routed by the path of an absolute URI, and after decoding it
+        let resp = roundtrip(
+            addr,
+            "GET http://example.com/users/42/x/../a%20b HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        );
+        assert!(resp.ends_with("\r\n\r\n42 a b"), "{}", resp);
+
+        shutdown.shutdown();
+        t.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn implied_methods() {
+        let mut server = Server::new("127.0.0.1:0".to_string());
+        for (methods, route) in [
+            (vec![Method::POST, Method::GET], "/items"),
+            (vec![Method::OPTIONS], "/custom"),
+        ] {
+            server
+                .handlers
+                .register(Handler::new(methods, Route::new(route).unwrap(), |_, w| {
+                    w.send(&mut Response::new(Status::Ok, "handler"))
+                }))
+                .unwrap();
+        }
+
+        let (addr, shutdown, t) = start(server);
+        let resp = roundtrip(
+            addr,
+            "DELETE /items HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        );
+        assert!(
+            resp.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"),
+            "{}",
+            resp
+        );
+        assert!(
+            resp.contains("Allow: GET, POST, HEAD, OPTIONS\r\n"),
+            "{}",
+            resp
+        );
+
+        let resp = roundtrip(
+            addr,
+            "OPTIONS /items HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        );
+        assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"), "{}", resp);
+        assert!(
+            resp.contains("Allow: GET, POST, HEAD, OPTIONS\r\n"),
+            "{}",
+            resp
+        );
+
+        let resp = roundtrip(
+            addr,
+            "OPTIONS /custom HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        );
+        assert!(resp.ends_with("\r\n\r\nhandler"), "{}", resp);
+
+        let resp = roundtrip(
+            addr,
+            "OPTIONS /other HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        );
+        assert!(resp.starts_with("HTTP/1.1 404 Not Found\r\n"), "{}", resp);
+
+        // the methods of all routes for the server as a whole
+        let resp = roundtrip(
+            addr,
+            "OPTIONS * HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        );
+        assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"), "{}", resp);
+        assert!(
+            resp.contains("Allow: GET, POST, HEAD, OPTIONS\r\n"),
+            "{}",
+            resp
+        );
+
+        shutdown.shutdown();
+        t.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn returning_handler() {
+        let mut server = Server::new("127.0.0.1:0".to_string());
+        server
+            .handlers
+            .register(Handler::returning(
+                vec![Method::GET],
+                Route::new("/users/:id").unwrap(),
+                |r| -> Result<_, ParamError> {
+                    let id: u32 = r.param_as("id")?;
+                    Ok((Status::Created, format!("user #{}", id)))
+                },
+            ))
+            .unwrap();
+        server.wrap(Tag("global"));
+        let (addr, shutdown, t) = start(server);
+
+        let resp = roundtrip(
+            addr,
+            "GET /users/7 HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        );
+        assert!(resp.starts_with("HTTP/1.1 201 Created\r\n"), "{}", resp);
+        // the hook of the middleware sees the returned response
+        assert!(resp.contains("X-After-global: 1\r\n"), "{}", resp);
+        assert!(resp.ends_with("\r\n\r\nuser #7"), "{}", resp);
+
+        let resp = roundtrip(
+            addr,
+            "GET /users/x HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        );
+        assert!(resp.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{}", resp);
+
+        shutdown.shutdown();
+        t.join().unwrap().unwrap();
+    }
+
+    /// Adds a header to every response and tags the request path.
+    struct Tag(&'static str);
+
+    impl<T: Write> Middleware<T> for Tag {
+        fn handle(
+            &self,
+            mut r: Request,
+            w: &mut ResponseWriter<T>,
+            next: Next<'_, T>,
+        ) -> Result<usize, std::io::Error> {
+            let tag = self.0;
+            r.headers.add(
+                "X-Tags".to_string(),
+                Some(match r.headers.get("x-tags".to_string()) {
+                    Ok(t) => format!("{},{}", t, tag),
+                    Err(_) => tag.to_string(),
+                }),
+            );
+            w.on_response(move |resp| {
+                resp.headers
+                    .add(format!("X-After-{}", tag), Some("1".to_string()));
+            });
+
+            next.run(r, w)
+        }
+    }
+
+    /// Rejects requests without an `Authorization` header.
+    struct RequireAuth;
+
+    impl<T: Write> Middleware<T> for RequireAuth {
+        fn handle(
+            &self,
+            r: Request,
+            w: &mut ResponseWriter<T>,
+            next: Next<'_, T>,
+        ) -> Result<usize, std::io::Error> {
+            if r.headers.get("authorization".to_string()).is_err() {
+                return w.send(&mut Response::new(Status::Unauthorized, "who are you?"));
+            }
+            next.run(r, w)
+        }
+    }
+
+    #[test]
+    fn middleware() {
+        let echo_tags = |r: Request, w: &mut ResponseWriter<TcpStream>| {
+            let tags = r.headers.get("x-tags".to_string()).unwrap_or_default();
+            w.send(&mut Response::new(Status::Ok, &tags))
+        };
+
+        let mut server = Server::new("127.0.0.1:0".to_string());
+        server.wrap(Tag("global"));
+        server
+            .handlers
+            .register(Handler::new(
+                vec![Method::GET],
+                Route::new("/open").unwrap(),
+                echo_tags,
+            ))
+            .unwrap();
+
+        let mut admin = Group::new("/admin").unwrap();
+        admin.wrap(RequireAuth);
+        admin.wrap(Tag("admin"));
+        admin.register(Handler::new(
+            vec![Method::GET],
+            Route::new("/").unwrap(),
+            echo_tags,
+        ));
+        server.handlers.register_group(admin).unwrap();
+
+        let (addr, shutdown, t) = start(server);
+
+        let resp = roundtrip(
+            addr,
+            "GET /open HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        );
+        assert!(resp.contains("X-After-global: 1\r\n"), "{}", resp);
+        assert!(!resp.contains("X-After-admin"), "{}", resp);
+        assert!(resp.ends_with("\r\n\r\nglobal"), "{}", resp);
+
+        let resp = roundtrip(
+            addr,
+            "GET /admin HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        );
+        assert!(
+            resp.starts_with("HTTP/1.1 401 Unauthorized\r\n"),
+            "{}",
+            resp
+        );
+        assert!(resp.contains("X-After-global: 1\r\n"), "{}", resp);
+
+        let resp = roundtrip(
+            addr,
+            "GET /admin HTTP/1.1\r\nHost: localhost\r\nAuthorization: yes\r\nConnection: close\r\n\r\n",
+        );
+        assert!(resp.contains("X-After-admin: 1\r\n"), "{}", resp);
+        assert!(resp.ends_with("\r\n\r\nglobal,admin"), "{}", resp);
+
+        shutdown.shutdown();
+        t.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn malformed_requests() {
+        let (addr, shutdown, t) = start(Server::new("127.0.0.1:0".to_string()));
+
+        let status = |raw: &str| {
+            let resp = roundtrip(addr, raw);
+            resp.lines().next().unwrap_or_default().to_string()
+        };
+        assert_eq!(status("GET /\r\n\r\n"), "HTTP/1.1 400 Bad Request");
+        assert_eq!(status("GET / HTTP/1.1\r\n\r\n"), "HTTP/1.1 400 Bad Request");
+        assert_eq!(
+            status("GET / HTTP/1.1\r\nHost: a\r\nHost: b\r\n\r\n"),
+            "HTTP/1.1 400 Bad Request"
+        );
+        assert_eq!(
+            status("GET / HTTP/1.1\r\nHost: a b\r\n\r\n"),
+            "HTTP/1.1 400 Bad Request"
+        );
+        // HTTP/1.0 doesn't need a Host
+        assert_eq!(status("GET / HTTP/1.0\r\n\r\n"), "HTTP/1.1 404 Not Found");
+        assert_eq!(
+            status("GET / HTTP/1.1 x\r\n\r\n"),
+            "HTTP/1.1 400 Bad Request"
+        );
+        assert_eq!(
+            status("GET / HTTP/1.1\r\nHost: localhost\r\nno colon\r\n\r\n"),
+            "HTTP/1.1 400 Bad Request"
+        );
+        assert_eq!(
+            status("POST / HTTP/1.1\r\nHost: localhost\r\n Content-Length: 3\r\n\r\nabc"),
+            "HTTP/1.1 400 Bad Request"
+        );
+        assert_eq!(
+            status("GET / HTTP/1.1\r\nHost: localhost\r\nFoo Bar: x\r\n\r\n"),
+            "HTTP/1.1 400 Bad Request"
+        );
+        assert_eq!(
+            status("POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: x\r\n\r\n"),
+            "HTTP/1.1 400 Bad Request"
+        );
+        assert_eq!(
+            status("POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: +3\r\n\r\nabc"),
+            "HTTP/1.1 400 Bad Request"
+        );
+        assert_eq!(
+            status("BREW / HTTP/1.1\r\nHost: localhost\r\n\r\n"),
+            "HTTP/1.1 501 Not Implemented"
+        );
+        assert_eq!(
+            status("GET / HTTP/2.0\r\n\r\n"),
+            "HTTP/1.1 505 HTTP Version Not Supported"
+        );
+        assert_eq!(status("GET / HTPP/1.1\r\n\r\n"), "HTTP/1.1 400 Bad Request");
+        assert_eq!(
+            status(&format!(
+                "GET /{} HTTP/1.1\r\nHost: localhost\r\n\r\n",
+                "a".repeat(10_000)
+            )),
+            "HTTP/1.1 414 URI Too Long"
+        );
+        assert_eq!(
+            status(&format!(
+                "GET / HTTP/1.1\r\nHost: localhost\r\n{}\r\n",
+                "X-Filler: abcdefghijklmnopqrstuvwxyz\r\n".repeat(2000)
+            )),
+            "HTTP/1.1 431 Request Header Fields Too Large"
+        );
+        assert_eq!(
+            status("POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: gzip\r\n\r\n"),
+            "HTTP/1.1 501 Not Implemented"
+        );
+
+        shutdown.shutdown();
+        t.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn request_limits() {
+        let mut server = Server::new("127.0.0.1:0".to_string());
+        server.config.limits = Limits {
+            max_request_line: 32,
+            max_header_bytes: 64,
+            max_headers: 3,
+            max_body_size: 4,
+        };
+        server
+            .handlers
+            .register(Handler::returning(
+                vec![Method::POST],
+                Route::new("/").unwrap(),
+                |r| r.payload,
+            ))
+            .unwrap();
+        let (addr, shutdown, t) = start(server);
+
+        let status = |raw: &str| {
+            let resp = roundtrip(addr, raw);
+            resp.lines().next().unwrap_or_default().to_string()
+        };
+        assert_eq!(
+            status("POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 4\r\nConnection: close\r\n\r\nabcd"),
+            "HTTP/1.1 200 OK"
+        );
+        assert_eq!(
+            status(&format!(
+                "GET /{} HTTP/1.1\r\nHost: localhost\r\n\r\n",
+                "a".repeat(20)
+            )),
+            "HTTP/1.1 414 URI Too Long"
+        );
+        assert_eq!(
+            status("GET / HTTP/1.1\r\nHost: localhost\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n"),
+            "HTTP/1.1 431 Request Header Fields Too Large"
+        );
+        assert_eq!(
+            status(&format!(
+                "GET / HTTP/1.1\r\nHost: localhost\r\nA: {}\r\n\r\n",
+                "a".repeat(60)
+            )),
+            "HTTP/1.1 431 Request Header Fields Too Large"
+        );
+        assert_eq!(
+            status("POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\n\r\nabcde"),
+            "HTTP/1.1 413 Content Too Large"
+        );
+        assert_eq!(
+            status("POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n3\r\ndef\r\n0\r\n\r\n"),
+            "HTTP/1.1 413 Content Too Large"
+        );
+        // trailer fields count against the header limits
+        assert_eq!(
+            status("POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n0\r\nA: 1\r\nB: 2\r\n\r\n"),
+            "HTTP/1.1 431 Request Header Fields Too Large"
+        );
+        assert_eq!(
+            status(&format!(
+                "POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n0\r\nA: {}\r\n\r\n",
+                "a".repeat(20)
+            )),
+            "HTTP/1.1 431 Request Header Fields Too Large"
+        );
+
+        shutdown.shutdown();
+        t.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn slow_and_truncated_requests() {
+        let mut server = Server::new("127.0.0.1:0".to_string());
+        server.config.request_timeout = Duration::from_millis(300);
+        server
+            .handlers
+            .register(Handler::returning(
+                vec![Method::POST],
+                Route::new("/").unwrap(),
+                |r| r.payload,
+            ))
+            .unwrap();
+        let (addr, shutdown, t) = start(server);
+
+        // sends `parts` with a pause in between and reads the response
+        let send = |parts: &[&str], pause: Duration, close: bool| {
+            let mut s = TcpStream::connect(addr).unwrap();
+            for p in parts {
+                s.write_all(p.as_bytes()).unwrap();
+                thread::sleep(pause);
+            }
+            if close {
+                s.shutdown(Shutdown::Write).unwrap();
+            }
+            let mut resp = String::new();
+            s.read_to_string(&mut resp).unwrap();
+            resp
+        };
+
+        // a body arriving in pieces is read completely
+        let resp = send(
+            &[
+                "POST / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: 6\r\n\r\nabc",
+                "def",
+            ],
+            Duration::from_millis(50),
+            false,
+        );
+        assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"), "{}", resp);
+        assert!(resp.ends_with("\r\n\r\nabcdef"), "{}", resp);
+
+        let resp = send(
+            &["POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 6\r\n\r\nabc"],
+            Duration::ZERO,
+            true,
+        );
+        assert!(resp.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{}", resp);
+
+        // trickling in a header a byte at a time doesn't extend the deadline
+        let start = Instant::now();
+        let trickle: Vec<String> = "POST / HTTP/1.1\r\nX-Slow"
+            .chars()
+            .map(String::from)
+            .collect();
+        let trickle: Vec<&str> = trickle.iter().map(String::as_str).collect();
+        let resp = send(&trickle, Duration::from_millis(20), false);
+        assert!(
+            resp.starts_with("HTTP/1.1 408 Request Timeout\r\n"),
+            "{}",
+            resp
+        );
+        assert!(start.elapsed() < Duration::from_secs(2));
+
+        shutdown.shutdown();
+        t.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn virtual_hosts() {
+        let mut server = Server::new("127.0.0.1:0".to_string());
+        for (host, name) in [
+            ("api.local", "api"),
+            ("*.api.local", "api subdomain"),
+            ("*.local", "local"),
+        ] {
+            server
+                .host(host)
+                .unwrap()
+                .register(Handler::returning(
+                    vec![Method::GET],
+                    Route::new("/").unwrap(),
+                    move |_| name,
+                ))
+                .unwrap();
+        }
+        server
+            .handlers
+            .register(Handler::returning(
+                vec![Method::GET],
+                Route::new("/").unwrap(),
+                |_| "default",
+            ))
+            .unwrap();
+        assert!(server.host("a.*.local").is_err());
+        let (addr, shutdown, t) = start(server);
+
+        let body = |target: &str, host: &str| {
+            let resp = roundtrip(
+                addr,
+                &format!(
+                    "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+                    target, host
+                ),
+            );
+            resp.split("\r\n\r\n")
+                .nth(1)
+                .unwrap_or_default()
+                .to_string()
+        };
+        assert_eq!(body("/", "api.local"), "api");
+        assert_eq!(body("/", "API.local:8080"), "api");
+        assert_eq!(body("/", "v1.api.local"), "api subdomain");
+        assert_eq!(body("/", "web.local."), "local");
+        assert_eq!(body("/", "example.com"), "default");
+        assert_eq!(body("/", "[::1]:80"), "default");
+        // the authority of an absolute-form target wins over Host
+        assert_eq!(body("http://api.local/", "example.com"), "api");
+
+        shutdown.shutdown();
+        t.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn bind_error() {
+        let bound = Server::new("127.0.0.1:0".to_string()).bind().unwrap();
+        let addr = bound.local_addr().to_string();
+
+        match Server::new(addr.clone()).bind() {
+            Err(ServerError::Bind { addr: a, .. }) => assert_eq!(a, addr),
+            _ => panic!("binding the same address twice should fail"),
+        }
+    }
+}
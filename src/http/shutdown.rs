@@ -0,0 +1,57 @@
+use std::{
+    net::{SocketAddr, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+/// Asks a running [`Server`](crate::Server) to stop.
+///
+/// The handle can be cloned and moved to other threads, e.g. into a signal
+/// handler. Once triggered the server stops accepting connections, finishes
+/// the requests in flight and returns from `run`.
+#[derive(Clone, Default)]
+pub struct ShutdownHandle {
+    inner: Arc<State>,
+}
+
+#[derive(Default)]
+struct State {
+    requested: AtomicBool,
+    addr: Mutex<Option<SocketAddr>>,
+}
+
+impl ShutdownHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn shutdown(&self) {
+        if self.inner.requested.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        // wake up the accept loop, which is blocked until the next connection
+        let addr = *self.inner.addr.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(mut addr) = addr {
+            if addr.ip().is_unspecified() {
+                addr.set_ip(match addr {
+                    SocketAddr::V4(_) => [127, 0, 0, 1].into(),
+                    SocketAddr::V6(_) => std::net::Ipv6Addr::LOCALHOST.into(),
+                });
+            }
+            let _ = TcpStream::connect_timeout(&addr, Duration::new(1, 0));
+        }
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.inner.requested.load(Ordering::SeqCst)
+    }
+
+    /// Records the address the server listens on so `shutdown` can wake it.
+    pub(crate) fn listening_on(&self, addr: SocketAddr) {
+        *self.inner.addr.lock().unwrap_or_else(|e| e.into_inner()) = Some(addr);
+    }
+}
@@ -0,0 +1,274 @@
+use std::{borrow::Cow, error::Error, fmt::Display};
+
+/// Defines `Status` with one variant per registered code and the lookups
+/// between variants, codes and reason phrases, so they come from one table.
+macro_rules! statuses {
+    ($($name:ident = $code:literal, $reason:literal;)+) => {
+        /// A response status code. Covers the codes in the IANA HTTP Status
+        /// Code Registry with their reason phrases from RFC 9110, other codes
+        /// can be sent with [`Status::custom`].
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        pub enum Status {
+            $($name,)+
+            /// Any other code, built with [`Status::custom`] or
+            /// [`Status::try_from`].
+            Custom(CustomStatus),
+        }
+
+        impl Status {
+            /// The numeric status code.
+            pub fn code(&self) -> u16 {
+                match self {
+                    $(Self::$name => $code,)+
+                    Self::Custom(c) => c.code,
+                }
+            }
+
+            /// The reason phrase sent after the code.
+            pub fn reason(&self) -> &str {
+                match self {
+                    $(Self::$name => $reason,)+
+                    Self::Custom(c) => &c.reason,
+                }
+            }
+
+            fn from_code(code: u16) -> Option<Self> {
+                match code {
+                    $($code => Some(Self::$name),)+
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+statuses! {
+    Continue = 100, "Continue";
+    SwitchingProtocols = 101, "Switching Protocols";
+    Processing = 102, "Processing";
+    EarlyHints = 103, "Early Hints";
+
+    Ok = 200, "OK";
+    Created = 201, "Created";
+    Accepted = 202, "Accepted";
+    NonAuthoritativeInformation = 203, "Non-Authoritative Information";
+    NoContent = 204, "No Content";
+    ResetContent = 205, "Reset Content";
+    PartialContent = 206, "Partial Content";
+    MultiStatus = 207, "Multi-Status";
+    AlreadyReported = 208, "Already Reported";
+    ImUsed = 226, "IM Used";
+
+    MultipleChoices = 300, "Multiple Choices";
+    MovedPermanently = 301, "Moved Permanently";
+    Found = 302, "Found";
+    SeeOther = 303, "See Other";
+    NotModified = 304, "Not Modified";
+    UseProxy = 305, "Use Proxy";
+    TemporaryRedirect = 307, "Temporary Redirect";
+    PermanentRedirect = 308, "Permanent Redirect";
+
+    BadRequest = 400, "Bad Request";
+    Unauthorized = 401, "Unauthorized";
+    PaymentRequired = 402, "Payment Required";
+    Forbidden = 403, "Forbidden";
+    NotFound = 404, "Not Found";
+    MethodNotAllowed = 405, "Method Not Allowed";
+    NotAcceptable = 406, "Not Acceptable";
+    ProxyAuthenticationRequired = 407, "Proxy Authentication Required";
+    RequestTimeout = 408, "Request Timeout";
+    Conflict = 409, "Conflict";
+    Gone = 410, "Gone";
+    LengthRequired = 411, "Length Required";
+    PreconditionFailed = 412, "Precondition Failed";
+    ContentTooLarge = 413, "Content Too Large";
+    UriTooLong = 414, "URI Too Long";
+    UnsupportedMediaType = 415, "Unsupported Media Type";
+    RangeNotSatisfiable = 416, "Range Not Satisfiable";
+    ExpectationFailed = 417, "Expectation Failed";
+    MisdirectedRequest = 421, "Misdirected Request";
+    UnprocessableContent = 422, "Unprocessable Content";
+    Locked = 423, "Locked";
+    FailedDependency = 424, "Failed Dependency";
+    TooEarly = 425, "Too Early";
+    UpgradeRequired = 426, "Upgrade Required";
+    PreconditionRequired = 428, "Precondition Required";
+    TooManyRequests = 429, "Too Many Requests";
+    RequestHeaderFieldsTooLarge = 431, "Request Header Fields Too Large";
+    UnavailableForLegalReasons = 451, "Unavailable For Legal Reasons";
+
+    InternalServerError = 500, "Internal Server Error";
+    NotImplemented = 501, "Not Implemented";
+    BadGateway = 502, "Bad Gateway";
+    ServiceUnavailable = 503, "Service Unavailable";
+    GatewayTimeout = 504, "Gateway Timeout";
+    HttpVersionNotSupported = 505, "HTTP Version Not Supported";
+    VariantAlsoNegotiates = 506, "Variant Also Negotiates";
+    InsufficientStorage = 507, "Insufficient Storage";
+    LoopDetected = 508, "Loop Detected";
+    NotExtended = 510, "Not Extended";
+    NetworkAuthenticationRequired = 511, "Network Authentication Required";
+}
+
+/// A status code without a variant of its own and its reason phrase. Only
+/// [`Status::custom`] and [`Status::try_from`] build one, so the code always
+/// has three digits and the phrase fits on the status line.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CustomStatus {
+    code: u16,
+    reason: Cow<'static, str>,
+}
+
+impl Status {
+    /// A status with any three digit code and reason phrase. Registered codes
+    /// become their variant, with its own reason phrase, so that e.g.
+    /// `Status::custom(404, "Gone Fishing")` equals [`Status::NotFound`].
+    ///
+    /// ```
+    /// # use server::Status;
+    /// let s = Status::custom(299, "Fine").unwrap();
+    /// assert_eq!(s.to_string(), "299 Fine");
+    /// assert!(Status::custom(42, "Answer").is_err());
+    /// ```
+    pub fn custom(code: u16, reason: impl Into<Cow<'static, str>>) -> Result<Self, InvalidStatus> {
+        if !(100..1000).contains(&code) {
+            return Err(InvalidStatus::Code(code));
+        }
+        // reason-phrase = *( HTAB / SP / VCHAR / obs-text ), RFC 9112 section 4
+        let reason = reason.into();
+        if reason.bytes().any(|b| b.is_ascii_control() && b != b'\t') {
+            return Err(InvalidStatus::Reason(reason.into_owned()));
+        }
+
+        Ok(Self::from_code(code).unwrap_or(Self::Custom(CustomStatus { code, reason })))
+    }
+
+    /// 1xx
+    pub fn is_informational(&self) -> bool {
+        (100..200).contains(&self.code())
+    }
+
+    /// 2xx
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.code())
+    }
+
+    /// 3xx
+    pub fn is_redirect(&self) -> bool {
+        (300..400).contains(&self.code())
+    }
+
+    /// 4xx
+    pub fn is_client_error(&self) -> bool {
+        (400..500).contains(&self.code())
+    }
+
+    /// 5xx
+    pub fn is_server_error(&self) -> bool {
+        (500..600).contains(&self.code())
+    }
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.code(), self.reason())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum InvalidStatus {
+    /// A number that isn't a three digit status code.
+    Code(u16),
+    /// A reason phrase with a line break or another control character.
+    Reason(String),
+}
+
+impl Display for InvalidStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Code(c) => write!(f, "{} is not a valid status code", c),
+            Self::Reason(r) => write!(f, "{:?} is not a valid reason phrase", r),
+        }
+    }
+}
+
+impl Error for InvalidStatus {}
+
+/// Registered codes become their variant, other three digit codes a
+/// [`Status::Custom`] without a reason phrase.
+impl TryFrom<u16> for Status {
+    type Error = InvalidStatus;
+
+    fn try_from(code: u16) -> Result<Self, Self::Error> {
+        Self::custom(code, "")
+    }
+}
+
+impl From<Status> for u16 {
+    fn from(s: Status) -> Self {
+        s.code()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CustomStatus, InvalidStatus, Status};
+
+    #[test]
+    fn codes_and_reasons() {
+        assert_eq!(Status::Ok.to_string(), "200 OK");
+        assert_eq!(Status::ContentTooLarge.to_string(), "413 Content Too Large");
+        assert_eq!(Status::custom(299, "Fine").unwrap().to_string(), "299 Fine");
+        assert_eq!(
+            Status::custom(299, format!("Fine {}", 1)).unwrap().reason(),
+            "Fine 1"
+        );
+        assert_eq!(u16::from(Status::PermanentRedirect), 308);
+    }
+
+    #[test]
+    fn from_u16() {
+        assert_eq!(Status::try_from(404), Ok(Status::NotFound));
+        assert_eq!(Status::try_from(226), Ok(Status::ImUsed));
+        assert_eq!(
+            Status::try_from(418),
+            Ok(Status::Custom(CustomStatus {
+                code: 418,
+                reason: "".into()
+            }))
+        );
+        assert_eq!(Status::try_from(99), Err(InvalidStatus::Code(99)));
+        assert_eq!(Status::try_from(1000), Err(InvalidStatus::Code(1000)));
+
+        // every registered code maps back to its own variant
+        for code in 100..600 {
+            if let Ok(s) = Status::try_from(code) {
+                assert_eq!(s.code(), code);
+            }
+        }
+    }
+
+    #[test]
+    fn custom() {
+        assert_eq!(Status::custom(404, "Gone Fishing"), Ok(Status::NotFound));
+        assert_eq!(Status::custom(42, "x"), Err(InvalidStatus::Code(42)));
+        assert_eq!(Status::custom(0, "x"), Err(InvalidStatus::Code(0)));
+        assert_eq!(
+            Status::custom(299, "Fine\r\nSet-Cookie: a=1"),
+            Err(InvalidStatus::Reason("Fine\r\nSet-Cookie: a=1".to_string()))
+        );
+        assert!(Status::custom(299, "Fine\tThanks").is_ok());
+    }
+
+    #[test]
+    fn classes() {
+        assert!(Status::EarlyHints.is_informational());
+        assert!(Status::NoContent.is_success());
+        assert!(Status::SeeOther.is_redirect());
+        assert!(Status::TooManyRequests.is_client_error());
+        assert!(Status::GatewayTimeout.is_server_error());
+        let closed = Status::custom(499, "Client Closed").unwrap();
+        assert!(!closed.is_server_error());
+        assert!(closed.is_client_error());
+    }
+}
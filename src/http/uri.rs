@@ -1,49 +1,376 @@
-use std::collections::{hash_map::RandomState, HashMap};
+use std::{error::Error, fmt::Display};
 
-// TODO: Add support for multiple values with the same name
-type QueryParams = HashMap<String, Option<String>, RandomState>;
+use super::QueryParams;
 
+/// The form of a request target, see RFC 9112 section 3.2.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TargetForm {
+    /// `/path?query`, the usual form.
+    #[default]
+    Origin,
+    /// `http://host:port/path?query`, sent to proxies.
+    Absolute,
+    /// `host:port`, only for `CONNECT`.
+    Authority,
+    /// `*`, only for `OPTIONS` requests about the server as a whole.
+    Asterisk,
+}
+
+/// A request target, or any other URI with an absolute path.
 #[derive(Default, Debug)]
 pub struct Uri {
+    pub form: TargetForm,
+    /// The lowercased scheme, e.g. `http`, of an absolute URI.
+    pub scheme: Option<String>,
+    /// `host:port` as sent, for the absolute and authority forms.
+    pub authority: Option<String>,
+    /// The lowercased host of the authority, IPv6 addresses keep their
+    /// brackets.
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    /// The decoded and normalized path, which handlers are routed by: escapes
+    /// are decoded, `.` and `..` segments are resolved and repeated slashes
+    /// are merged, e.g. `/files/a/../my%20doc` becomes `/files/my doc`. It is
+    /// `*` for the asterisk form and empty for the authority form.
     pub path: String,
+    /// The path as it was sent.
+    pub raw_path: String,
+    /// The decoded query parameters.
     pub params: QueryParams,
+    /// The fragment, which clients don't send in request targets but which
+    /// may be part of URIs in headers such as `Referer`.
+    pub fragment: Option<String>,
     pub raw: String,
 }
 
+#[derive(Debug, PartialEq, Eq)]
+pub enum UriError {
+    /// A `%` that isn't followed by two hex digits.
+    InvalidEscape,
+    /// Decoding produced bytes that aren't UTF-8.
+    InvalidUtf8,
+    /// The path contains an encoded `/` or NUL, which can't be told apart
+    /// from a separator, or the end of the path, once decoded.
+    ForbiddenEscape,
+    /// The host or port is missing or malformed, or the authority has user
+    /// information, which HTTP doesn't allow.
+    InvalidAuthority,
+    /// The target has none of the four forms, e.g. a path without a leading
+    /// `/`.
+    InvalidForm,
+}
+
+impl Display for UriError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidEscape => write!(f, "invalid percent-encoding"),
+            Self::InvalidUtf8 => write!(f, "percent-encoded bytes aren't valid UTF-8"),
+            Self::ForbiddenEscape => write!(f, "path contains an encoded '/' or NUL"),
+            Self::InvalidAuthority => write!(f, "invalid host or port"),
+            Self::InvalidForm => write!(f, "not a valid request target"),
+        }
+    }
+}
+
+impl Error for UriError {}
+
 impl std::str::FromStr for Uri {
-    type Err = ();
+    type Err = UriError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let (path, query) = match s.split_once('?') {
-            Some(p) => p,
-            None => (s, ""),
+        if s == "*" {
+            return Ok(Uri {
+                form: TargetForm::Asterisk,
+                path: s.to_string(),
+                raw_path: s.to_string(),
+                raw: s.to_string(),
+                ..Default::default()
+            });
+        }
+
+        let (rest, fragment) = match s.split_once('#') {
+            Some((r, f)) => (r, Some(decode(f, false)?)),
+            None => (s, None),
         };
+        let (rest, query) = rest.split_once('?').unwrap_or((rest, ""));
 
-        let mut params: QueryParams = HashMap::new();
-        for p in query.split('&') {
-            let Some((k,v)) = p.split_once('=').or(Some((p, ""))) else {
-                continue;
+        let mut uri = Uri {
+            params: QueryParams::parse(query)?,
+            fragment,
+            raw: s.to_string(),
+            ..Default::default()
+        };
+
+        let raw_path = if rest.starts_with('/') {
+            rest
+        } else if let Some((scheme, hier)) = split_scheme(rest) {
+            // http://authority/path
+            let hier = hier.strip_prefix("//").ok_or(UriError::InvalidForm)?;
+            let (authority, path) = match hier.find('/') {
+                Some(i) => hier.split_at(i),
+                None => (hier, ""),
             };
+            uri.form = TargetForm::Absolute;
+            uri.scheme = Some(scheme.to_ascii_lowercase());
+            uri.set_authority(authority, false)?;
+
+            path
+        } else {
+            // host:port, with nothing else allowed
+            if s.contains(['?', '#', '/']) {
+                return Err(UriError::InvalidForm);
+            }
+            uri.form = TargetForm::Authority;
+            uri.set_authority(rest, true)?;
 
-            params.insert(
-                k.to_string(),
-                match v {
-                    "" => None,
-                    s => Some(s.to_string()),
-                },
-            );
+            ""
+        };
+
+        uri.raw_path = raw_path.to_string();
+        uri.path = match (uri.form, raw_path) {
+            (TargetForm::Authority, _) => String::new(),
+            // http://host?q has the path /
+            (_, "") => "/".to_string(),
+            (_, p) => normalize_path(p)?,
+        };
+
+        Ok(uri)
+    }
+}
+
+impl Uri {
+    fn set_authority(&mut self, authority: &str, needs_port: bool) -> Result<(), UriError> {
+        let (host, port) = parse_authority(authority)?;
+        if host.is_empty() || (needs_port && port.is_none()) {
+            return Err(UriError::InvalidAuthority);
         }
 
-        Ok(Uri {
-            path: path.to_string(),
-            params,
-            raw: s.to_string(),
-        })
+        self.authority = Some(authority.to_string());
+        self.host = Some(host.to_ascii_lowercase());
+        self.port = port;
+
+        Ok(())
     }
 }
 
-impl std::string::ToString for Uri {
-    fn to_string(&self) -> String {
-        self.raw.to_string()
+/// Splits `scheme:rest` if `s` starts with a valid scheme (RFC 3986 section
+/// 3.1) followed by `://`. `host:port` isn't taken for a scheme as the port
+/// isn't followed by `//`.
+fn split_scheme(s: &str) -> Option<(&str, &str)> {
+    let (scheme, rest) = s.split_once(':')?;
+    let mut chars = scheme.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+
+    (valid && rest.starts_with("//")).then_some((scheme, rest))
+}
+
+/// Splits `host[:port]`, where the host may be an IPv6 literal in brackets.
+pub(crate) fn parse_authority(authority: &str) -> Result<(&str, Option<u16>), UriError> {
+    if authority.contains('@') {
+        return Err(UriError::InvalidAuthority);
+    }
+
+    let (host, port) = if authority.starts_with('[') {
+        let end = authority.find(']').ok_or(UriError::InvalidAuthority)? + 1;
+        let (host, rest) = authority.split_at(end);
+        match rest {
+            "" => (host, None),
+            r => (
+                host,
+                Some(r.strip_prefix(':').ok_or(UriError::InvalidAuthority)?),
+            ),
+        }
+    } else {
+        match authority.split_once(':') {
+            Some((h, p)) => (h, Some(p)),
+            None => (authority, None),
+        }
+    };
+
+    let valid_host = host.starts_with('[')
+        || host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_' | '~' | '%'));
+    if !valid_host {
+        return Err(UriError::InvalidAuthority);
+    }
+
+    let port = match port {
+        // an empty port is allowed and means the default one
+        None | Some("") => None,
+        Some(p) if p.bytes().all(|b| b.is_ascii_digit()) => {
+            Some(p.parse().map_err(|_| UriError::InvalidAuthority)?)
+        }
+        Some(_) => return Err(UriError::InvalidAuthority),
+    };
+
+    Ok((host, port))
+}
+
+impl std::fmt::Display for Uri {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+
+/// Decodes `%XX` escapes (RFC 3986 section 2.1), and `+` as a space if
+/// `plus_as_space` is set, as in HTML form data.
+pub(crate) fn decode(s: &str, plus_as_space: bool) -> Result<String, UriError> {
+    if !s.contains(['%', '+']) {
+        return Ok(s.to_string());
+    }
+
+    let mut out = Vec::with_capacity(s.len());
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'%' => {
+                let hex = [bytes.next(), bytes.next()];
+                let [Some(hi), Some(lo)] = hex.map(|h| h.and_then(|h| (h as char).to_digit(16)))
+                else {
+                    return Err(UriError::InvalidEscape);
+                };
+                out.push((hi * 16 + lo) as u8);
+            }
+            b'+' if plus_as_space => out.push(b' '),
+            b => out.push(b),
+        }
+    }
+
+    String::from_utf8(out).map_err(|_| UriError::InvalidUtf8)
+}
+
+/// Decodes a path and removes dot segments (RFC 3986 section 5.2.4) and
+/// empty segments. A trailing slash is kept. Paths that don't start with `/`
+/// are only decoded.
+fn normalize_path(raw: &str) -> Result<String, UriError> {
+    let lower = raw.to_ascii_lowercase();
+    if lower.contains("%2f") || lower.contains("%00") {
+        return Err(UriError::ForbiddenEscape);
+    }
+    let decoded = decode(raw, false)?;
+    let Some(rest) = decoded.strip_prefix('/') else {
+        return Ok(decoded);
+    };
+
+    let mut segments: Vec<&str> = vec![];
+    let mut trailing_slash = false;
+    for s in rest.split('/') {
+        trailing_slash = matches!(s, "" | "." | "..");
+        match s {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            s => segments.push(s),
+        }
+    }
+
+    let mut path = format!("/{}", segments.join("/"));
+    if trailing_slash && !segments.is_empty() {
+        path.push('/');
+    }
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TargetForm, Uri, UriError};
+
+    fn path(s: &str) -> Result<String, UriError> {
+        s.parse::<Uri>().map(|u| u.path)
+    }
+
+    #[test]
+    fn decode_path() {
+        assert_eq!(path("/files/my%20doc").unwrap(), "/files/my doc");
+        assert_eq!(path("/a+b").unwrap(), "/a+b");
+        assert_eq!(path("/caf%C3%A9").unwrap(), "/café");
+        assert_eq!(path("/%7e%7E").unwrap(), "/~~");
+
+        assert_eq!(path("/a%2").unwrap_err(), UriError::InvalidEscape);
+        assert_eq!(path("/a%zz").unwrap_err(), UriError::InvalidEscape);
+        assert_eq!(path("/%ff").unwrap_err(), UriError::InvalidUtf8);
+        assert_eq!(path("/a%2Fb").unwrap_err(), UriError::ForbiddenEscape);
+        assert_eq!(path("/a%00").unwrap_err(), UriError::ForbiddenEscape);
+    }
+
+    #[test]
+    fn normalize_path() {
+        assert_eq!(path("/").unwrap(), "/");
+        assert_eq!(path("/a/../b").unwrap(), "/b");
+        assert_eq!(path("/a/./b/").unwrap(), "/a/b/");
+        assert_eq!(path("/a/b/..").unwrap(), "/a/");
+        assert_eq!(path("/../../etc/passwd").unwrap(), "/etc/passwd");
+        assert_eq!(path("/a/%2e%2E/b").unwrap(), "/b");
+        assert_eq!(path("//a///b").unwrap(), "/a/b");
+        assert_eq!(path("/a/..").unwrap(), "/");
+
+        let u: Uri = "/a//b/../c?x=1".parse().unwrap();
+        assert_eq!(u.raw_path, "/a//b/../c");
+        assert_eq!(u.raw, "/a//b/../c?x=1");
+    }
+
+    #[test]
+    fn decode_query() {
+        let u: Uri = "/search?q=a%26b&name=John+Doe&empty&k%3D=v"
+            .parse()
+            .unwrap();
+
+        assert_eq!(u.params.get("q"), Some("a&b"));
+        assert_eq!(u.params.get("name"), Some("John Doe"));
+        assert_eq!(u.params.get("empty"), Some(""));
+        assert_eq!(u.params.get("k="), Some("v"));
+        assert_eq!(u.params.len(), 4);
+
+        assert_eq!("/?q=%".parse::<Uri>().unwrap_err(), UriError::InvalidEscape);
+    }
+
+    #[test]
+    fn target_forms() {
+        let u: Uri = "/a?b=c".parse().unwrap();
+        assert_eq!(u.form, TargetForm::Origin);
+        assert_eq!((u.host, u.port), (None, None));
+
+        let u: Uri = "HTTP://Example.COM:8080/a/../b?x=1#top".parse().unwrap();
+        assert_eq!(u.form, TargetForm::Absolute);
+        assert_eq!(u.scheme.as_deref(), Some("http"));
+        assert_eq!(u.authority.as_deref(), Some("Example.COM:8080"));
+        assert_eq!(u.host.as_deref(), Some("example.com"));
+        assert_eq!(u.port, Some(8080));
+        assert_eq!(u.path, "/b");
+        assert_eq!(u.params.get("x"), Some("1"));
+        assert_eq!(u.fragment.as_deref(), Some("top"));
+
+        let u: Uri = "http://[::1]?q".parse().unwrap();
+        assert_eq!(u.host.as_deref(), Some("[::1]"));
+        assert_eq!(u.port, None);
+        assert_eq!(u.path, "/");
+
+        let u: Uri = "example.com:443".parse().unwrap();
+        assert_eq!(u.form, TargetForm::Authority);
+        assert_eq!(u.host.as_deref(), Some("example.com"));
+        assert_eq!(u.port, Some(443));
+        assert_eq!(u.path, "");
+
+        let u: Uri = "*".parse().unwrap();
+        assert_eq!(u.form, TargetForm::Asterisk);
+        assert_eq!(u.path, "*");
+    }
+
+    #[test]
+    fn invalid_targets() {
+        let err = |s: &str| s.parse::<Uri>().unwrap_err();
+
+        assert_eq!(err("a/b"), UriError::InvalidForm);
+        assert_eq!(err("http:/a"), UriError::InvalidForm);
+        assert_eq!(err("example.com"), UriError::InvalidAuthority);
+        assert_eq!(err("example.com:https"), UriError::InvalidAuthority);
+        assert_eq!(err("example.com:99999"), UriError::InvalidAuthority);
+        assert_eq!(err("http://user@example.com/"), UriError::InvalidAuthority);
+        assert_eq!(err("http:///a"), UriError::InvalidAuthority);
+        assert_eq!(err("http://[::1/"), UriError::InvalidAuthority);
     }
 }
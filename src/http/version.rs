@@ -1,15 +1,15 @@
-#[derive(Default, PartialEq, Eq, Debug)]
+#[derive(Default, PartialEq, Eq, Debug, Clone, Copy)]
 pub enum HttpVersion {
     HTTP1_0,
     #[default]
     HTTP1_1,
 }
 
-impl std::string::ToString for HttpVersion {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for HttpVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            HttpVersion::HTTP1_0 => String::from("HTTP/1.0"),
-            HttpVersion::HTTP1_1 => String::from("HTTP/1.1"),
+            HttpVersion::HTTP1_0 => write!(f, "HTTP/1.0"),
+            HttpVersion::HTTP1_1 => write!(f, "HTTP/1.1"),
         }
     }
 }
@@ -0,0 +1,173 @@
+use std::{error::Error, fmt::Display, io::Write, str::FromStr};
+
+use super::Handlers;
+
+/// A host name virtual hosts are selected by, either exact (`api.local`) or a
+/// wildcard for all subdomains (`*.api.local`). The wildcard matches any
+/// number of labels, e.g. `a.b.api.local`, but not `api.local` itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostPattern {
+    /// Lowercased, without the `*.` of a wildcard.
+    name: String,
+    wildcard: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum HostPatternError {
+    Empty,
+    /// A `*` somewhere else than as the whole first label.
+    MisplacedWildcard,
+    InvalidChar(char),
+}
+
+impl Display for HostPatternError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Empty => write!(f, "host pattern is empty"),
+            Self::MisplacedWildcard => write!(f, "wildcard has to be the first label"),
+            Self::InvalidChar(c) => write!(f, "host pattern contains '{}'", c),
+        }
+    }
+}
+
+impl Error for HostPatternError {}
+
+impl HostPattern {
+    pub fn new(pattern: &str) -> Result<Self, HostPatternError> {
+        let (name, wildcard) = match pattern.strip_prefix("*.") {
+            Some(n) => (n, true),
+            None => (pattern, false),
+        };
+
+        if name.is_empty() {
+            return Err(HostPatternError::Empty);
+        }
+        if let Some(c) = name
+            .chars()
+            .find(|c| !c.is_ascii_alphanumeric() && !matches!(c, '-' | '.' | '_'))
+        {
+            return Err(match c {
+                '*' => HostPatternError::MisplacedWildcard,
+                c => HostPatternError::InvalidChar(c),
+            });
+        }
+
+        Ok(Self {
+            name: name.to_ascii_lowercase(),
+            wildcard,
+        })
+    }
+
+    /// Whether `host`, lowercased and without a port, matches.
+    pub fn matches(&self, host: &str) -> bool {
+        if !self.wildcard {
+            return host == self.name;
+        }
+
+        host.strip_suffix(&self.name)
+            .is_some_and(|sub| sub.len() > 1 && sub.ends_with('.'))
+    }
+}
+
+impl FromStr for HostPattern {
+    type Err = HostPatternError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s)
+    }
+}
+
+impl Display for HostPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.wildcard {
+            true => write!(f, "*.{}", self.name),
+            false => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// Handler tables by host name.
+pub(crate) struct VirtualHosts<T: Write> {
+    hosts: Vec<(HostPattern, Handlers<T>)>,
+}
+
+impl<T: Write> Default for VirtualHosts<T> {
+    fn default() -> Self {
+        Self { hosts: vec![] }
+    }
+}
+
+impl<T: Write> VirtualHosts<T> {
+    /// The handlers for `pattern`, added if there are none yet.
+    pub fn get_or_insert(&mut self, pattern: HostPattern) -> &mut Handlers<T> {
+        let i = match self.hosts.iter().position(|(p, _)| *p == pattern) {
+            Some(i) => i,
+            None => {
+                self.hosts.push((pattern, Handlers::new()));
+                self.hosts.len() - 1
+            }
+        };
+
+        &mut self.hosts[i].1
+    }
+
+    /// The handlers for `host`. An exact name wins over wildcards, and a
+    /// wildcard over the longer of two names wins over the other.
+    pub fn select(&self, host: &str) -> Option<&Handlers<T>> {
+        self.hosts
+            .iter()
+            .filter(|(p, _)| p.matches(host))
+            .max_by_key(|(p, _)| (!p.wildcard, p.name.len()))
+            .map(|(_, h)| h)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HostPattern, HostPatternError, VirtualHosts};
+
+    #[test]
+    fn patterns() {
+        let p = HostPattern::new("*.API.local").unwrap();
+        assert_eq!(p.to_string(), "*.api.local");
+        assert!(p.matches("v1.api.local"));
+        assert!(p.matches("a.b.api.local"));
+        assert!(!p.matches("api.local"));
+        assert!(!p.matches("xapi.local"));
+
+        let p = HostPattern::new("api.local").unwrap();
+        assert!(p.matches("api.local"));
+        assert!(!p.matches("v1.api.local"));
+
+        assert_eq!(HostPattern::new("*."), Err(HostPatternError::Empty));
+        assert_eq!(
+            HostPattern::new("a.*.local"),
+            Err(HostPatternError::MisplacedWildcard)
+        );
+        assert_eq!(
+            HostPattern::new("api.local:80"),
+            Err(HostPatternError::InvalidChar(':'))
+        );
+    }
+
+    #[test]
+    fn select() {
+        let mut hosts = VirtualHosts::<Vec<u8>>::default();
+        for p in ["*.local", "*.api.local", "v1.api.local"] {
+            hosts.get_or_insert(p.parse().unwrap());
+        }
+
+        let selected = |host: &str| {
+            let h = hosts.select(host)? as *const _;
+            hosts
+                .hosts
+                .iter()
+                .find(|(_, t)| std::ptr::eq(t, h))
+                .map(|(p, _)| p.to_string())
+        };
+        assert_eq!(selected("v1.api.local").unwrap(), "v1.api.local");
+        assert_eq!(selected("v2.api.local").unwrap(), "*.api.local");
+        assert_eq!(selected("api.local").unwrap(), "*.local");
+        assert_eq!(selected("example.com"), None);
+    }
+}
@@ -0,0 +1,305 @@
+use std::io::Write;
+
+use super::{HttpVersion, Method, Response};
+
+type ResponseHook = Box<dyn FnOnce(&mut Response)>;
+
+/// Writes the responses for the requests read from one connection.
+///
+/// Handlers send their [`Response`] through the writer instead of writing to
+/// the stream directly, which lets the server decide on the `Connection`
+/// header and whether the connection is kept open afterwards. The writer also
+/// knows the request method, and leaves out the body when answering `HEAD`.
+pub struct ResponseWriter<W: Write> {
+    stream: W,
+    method: Method,
+    version: HttpVersion,
+    keep_alive: bool,
+    hooks: Vec<ResponseHook>,
+}
+
+impl<W: Write> ResponseWriter<W> {
+    pub fn new(stream: W, version: HttpVersion, keep_alive: bool) -> Self {
+        Self {
+            stream,
+            method: Method::default(),
+            version,
+            keep_alive,
+            hooks: vec![],
+        }
+    }
+
+    /// Resets the writer for the next request on the same connection.
+    pub(crate) fn start(&mut self, method: Method, version: HttpVersion, keep_alive: bool) {
+        self.method = method;
+        self.version = version;
+        self.keep_alive = keep_alive;
+        self.hooks.clear();
+    }
+
+    /// Registers a function that may change the response before it is sent,
+    /// no matter who sends it. Hooks registered later run first, so a
+    /// middleware sees the response after the middleware it wraps.
+    pub fn on_response<F: FnOnce(&mut Response) + 'static>(&mut self, f: F) {
+        self.hooks.push(Box::new(f));
+    }
+
+    /// The method of the request being answered.
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    /// Whether the connection stays open after the current response.
+    pub fn keep_alive(&self) -> bool {
+        self.keep_alive
+    }
+
+    /// Closes the connection once the current response has been sent.
+    pub fn close(&mut self) {
+        self.keep_alive = false;
+    }
+
+    /// Sends `resp` with a `Content-Length` header. For `HEAD` requests only
+    /// the status line and headers are sent.
+    pub fn send(&mut self, resp: &mut Response) -> Result<usize, std::io::Error> {
+        self.run_hooks(resp);
+        self.set_connection(resp);
+
+        if self.method == Method::HEAD {
+            resp.set_content_length();
+            return resp.write_head_to(&mut self.stream);
+        }
+        resp.write_to(&mut self.stream)
+    }
+
+    /// Sends the status line and headers of `resp` and returns a writer for
+    /// the body, which is then sent as it is written instead of being
+    /// buffered in `resp.payload`. Anything already in `resp.payload` is sent
+    /// first.
+    ///
+    /// HTTP/1.1 bodies are sent with `Transfer-Encoding: chunked`; for
+    /// HTTP/1.0 the end of the body is marked by closing the connection.
+    pub fn stream(&mut self, resp: &mut Response) -> Result<BodyWriter<'_, W>, std::io::Error> {
+        self.run_hooks(resp);
+        resp.headers.remove("content-length");
+        let chunked = self.version == HttpVersion::HTTP1_1;
+        let discard = self.method == Method::HEAD;
+        if chunked {
+            resp.headers
+                .add("Transfer-Encoding".to_string(), Some("chunked".to_string()));
+        } else if !discard {
+            self.keep_alive = false;
+        }
+        self.set_connection(resp);
+
+        let head = resp.write_head_to(&mut self.stream)?;
+        let mut body = BodyWriter {
+            stream: &mut self.stream,
+            chunked,
+            discard,
+            written: head,
+            finished: false,
+        };
+        if !resp.payload.is_empty() {
+            body.write_all(&resp.payload)?;
+        }
+
+        Ok(body)
+    }
+
+    fn run_hooks(&mut self, resp: &mut Response) {
+        while let Some(hook) = self.hooks.pop() {
+            hook(resp);
+        }
+    }
+
+    fn set_connection(&mut self, resp: &mut Response) {
+        // a handler asking for the connection to be closed wins
+        if resp
+            .headers
+            .get("connection".to_string())
+            .is_ok_and(|v| v.eq_ignore_ascii_case("close"))
+        {
+            self.keep_alive = false;
+        }
+
+        match (self.keep_alive, self.version) {
+            (false, _) => {
+                resp.headers
+                    .add("Connection".to_string(), Some("close".to_string()));
+            }
+            // HTTP/1.0 connections are only persistent if both sides say so
+            (true, HttpVersion::HTTP1_0) => {
+                resp.headers
+                    .add("Connection".to_string(), Some("keep-alive".to_string()));
+            }
+            (true, HttpVersion::HTTP1_1) => {}
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.stream
+    }
+
+    pub fn into_inner(self) -> W {
+        self.stream
+    }
+}
+
+/// Writes a streamed response body, see [`ResponseWriter::stream`].
+///
+/// Every `write` is sent as one chunk. The body has to be ended with
+/// [`finish`](BodyWriter::finish), which is also done on drop, ignoring errors.
+/// When answering `HEAD` everything written is discarded.
+pub struct BodyWriter<'a, W: Write> {
+    stream: &'a mut W,
+    chunked: bool,
+    discard: bool,
+    written: usize,
+    finished: bool,
+}
+
+impl<W: Write> BodyWriter<'_, W> {
+    /// Ends the body and returns the number of bytes written for the whole
+    /// response.
+    pub fn finish(mut self) -> Result<usize, std::io::Error> {
+        self.end()?;
+
+        Ok(self.written)
+    }
+
+    fn end(&mut self) -> Result<(), std::io::Error> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+
+        if self.chunked && !self.discard {
+            self.stream.write_all(b"0\r\n\r\n")?;
+            self.written += 5;
+        }
+        self.stream.flush()
+    }
+}
+
+impl<W: Write> Write for BodyWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // an empty chunk would end the body
+        if buf.is_empty() || self.discard {
+            return Ok(buf.len());
+        }
+
+        if self.chunked {
+            let mut chunk = format!("{:x}\r\n", buf.len()).into_bytes();
+            chunk.extend_from_slice(buf);
+            chunk.extend_from_slice(b"\r\n");
+            self.stream.write_all(&chunk)?;
+            self.written += chunk.len();
+        } else {
+            self.stream.write_all(buf)?;
+            self.written += buf.len();
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl<W: Write> Drop for BodyWriter<'_, W> {
+    fn drop(&mut self) {
+        let _ = self.end();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use crate::{HttpVersion, Method, Response, ResponseWriter, Status};
+
+    fn sent(version: HttpVersion, keep_alive: bool) -> String {
+        let mut w = ResponseWriter::new(Cursor::new(Vec::<u8>::new()), version, keep_alive);
+        w.send(&mut Response::new(Status::Ok, "")).unwrap();
+
+        String::from_utf8(w.into_inner().into_inner()).unwrap()
+    }
+
+    #[test]
+    fn connection_header() {
+        assert!(!sent(HttpVersion::HTTP1_1, true).contains("Connection:"));
+        assert!(sent(HttpVersion::HTTP1_1, false).contains("Connection: close\r\n"));
+        assert!(sent(HttpVersion::HTTP1_0, true).contains("Connection: keep-alive\r\n"));
+    }
+
+    #[test]
+    fn handler_closes_connection() {
+        let mut w = ResponseWriter::new(Vec::<u8>::new(), HttpVersion::HTTP1_1, true);
+        let mut resp = Response::new(Status::Ok, "");
+        resp.headers
+            .add("Connection".to_string(), Some("close".to_string()));
+
+        w.send(&mut resp).unwrap();
+        assert!(!w.keep_alive());
+    }
+
+    #[test]
+    fn stream_chunked() {
+        let mut w = ResponseWriter::new(Vec::<u8>::new(), HttpVersion::HTTP1_1, true);
+        let mut resp = Response::new(Status::Ok, "ab");
+
+        let mut body = w.stream(&mut resp).unwrap();
+        body.write_all(b"cde").unwrap();
+        body.write_all(b"").unwrap();
+        let n = body.finish().unwrap();
+
+        assert!(w.keep_alive());
+        let out = String::from_utf8(w.into_inner()).unwrap();
+        assert_eq!(out.len(), n);
+        assert!(out.contains("Transfer-Encoding: chunked\r\n"));
+        assert!(!out.contains("Content-Length"));
+        assert!(out.ends_with("\r\n\r\n2\r\nab\r\n3\r\ncde\r\n0\r\n\r\n"));
+    }
+
+    #[test]
+    fn head_has_no_body() {
+        let head_writer = || {
+            let mut w = ResponseWriter::new(Vec::<u8>::new(), HttpVersion::HTTP1_1, true);
+            w.start(Method::HEAD, HttpVersion::HTTP1_1, true);
+            w
+        };
+
+        let mut w = head_writer();
+        w.send(&mut Response::new(Status::Ok, "hello")).unwrap();
+        let out = String::from_utf8(w.into_inner()).unwrap();
+        assert!(out.contains("Content-Length: 5\r\n"), "{}", out);
+        assert!(out.ends_with("\r\n\r\n"), "{}", out);
+
+        let mut w = head_writer();
+        let mut body = w.stream(&mut Response::new(Status::Ok, "abc")).unwrap();
+        body.write_all(b"def").unwrap();
+        body.finish().unwrap();
+        let out = String::from_utf8(w.into_inner()).unwrap();
+        assert!(out.contains("Transfer-Encoding: chunked\r\n"), "{}", out);
+        assert!(out.ends_with("\r\n\r\n"), "{}", out);
+        assert!(!out.contains("abc") && !out.contains("def"), "{}", out);
+    }
+
+    #[test]
+    fn stream_http1_0_closes() {
+        let mut w = ResponseWriter::new(Vec::<u8>::new(), HttpVersion::HTTP1_0, true);
+        let mut resp = Response::new(Status::Ok, "");
+
+        let mut body = w.stream(&mut resp).unwrap();
+        body.write_all(b"abc").unwrap();
+        drop(body);
+
+        assert!(!w.keep_alive());
+        let out = String::from_utf8(w.into_inner()).unwrap();
+        assert!(out.contains("Connection: close\r\n"));
+        assert!(!out.contains("Transfer-Encoding"));
+        assert!(out.ends_with("\r\n\r\nabc"));
+    }
+}